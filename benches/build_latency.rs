@@ -0,0 +1,170 @@
+//! measures the latency of a payload job that builds a payload from hundreds of bundles. run with
+//! `cargo bench --bench build_latency`.
+
+use std::future::poll_fn;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip2930::AccessList},
+        Eip1559TransactionRequest, NameOrAddress, H160 as EthersAddress,
+    },
+};
+use evangelion::builder::{algorithm::BundlesFirst, Builder, BuilderConfig, Job};
+use evangelion::bundle::{Bundle, BundleId, BundleRequest};
+use futures_util::FutureExt;
+use reth_payload_builder::{PayloadBuilderAttributes, PayloadId, PayloadJob, PayloadJobGenerator};
+use reth_primitives::{
+    Address, Block, Bytes, ChainSpecBuilder, Header, SealedHeader, TransactionSigned,
+    TransactionSignedEcRecovered, H256, U256,
+};
+use reth_provider::test_utils::{ExtendedAccount, MockEthProvider};
+use reth_transaction_pool::noop::NoopTransactionPool;
+use tokio::{runtime::Runtime, sync::mpsc};
+
+const NUM_BUNDLES: usize = 500;
+/// every fifth bundle fails part-way through, so the payload merges the rest
+const NUM_MERGED: usize = NUM_BUNDLES - NUM_BUNDLES / 5;
+const TRANSFER_GAS_LIMIT: u64 = 21000;
+
+fn transfer(from: &LocalWallet, to: EthersAddress, nonce: u64) -> TransactionSignedEcRecovered {
+    let tx = Eip1559TransactionRequest::new()
+        .from(from.address())
+        .to(NameOrAddress::Address(to))
+        .gas(TRANSFER_GAS_LIMIT)
+        .max_fee_per_gas(10)
+        .max_priority_fee_per_gas(10)
+        .value(1)
+        .data(ethers::types::Bytes::default())
+        .access_list(AccessList::default())
+        .nonce(nonce)
+        .chain_id(from.chain_id());
+    let tx = TypedTransaction::Eip1559(tx);
+    let signature = from.sign_transaction_sync(&tx).expect("can sign tx");
+    let tx_encoded = tx.rlp_signed(&signature);
+    let tx = TransactionSigned::decode_enveloped(Bytes::from(tx_encoded.as_ref()))
+        .expect("can decode tx");
+    tx.into_ecrecovered().expect("can recover tx signer")
+}
+
+/// a chain with an (empty) genesis parent, along with `NUM_BUNDLES` transfer bundles for the
+/// block on top of it
+fn chain() -> (MockEthProvider, SealedHeader, Vec<Bundle>) {
+    let client = MockEthProvider::default();
+    let parent = Header {
+        gas_limit: 30000000,
+        base_fee_per_gas: Some(0),
+        ..Default::default()
+    }
+    .seal_slow();
+    let parent_block = Block {
+        header: parent.header.clone(),
+        ..Default::default()
+    };
+    client.add_header(parent.hash, parent.header.clone());
+    client.add_block(parent.hash, parent_block);
+
+    let receiver = EthersAddress::random();
+    let bundles = (0..NUM_BUNDLES)
+        .map(|id| {
+            let wallet = LocalWallet::new(&mut rand::thread_rng());
+            let account = ExtendedAccount::new(0, U256::from(10000000));
+            client.add_account(wallet.address().into(), account);
+
+            // the second transaction skips a nonce
+            let mut txs = vec![transfer(&wallet, receiver, 0)];
+            if id % 5 == 0 {
+                txs.push(transfer(&wallet, receiver, 2));
+            }
+            Bundle {
+                id: id as BundleId,
+                txs,
+                block_range: 1..=1,
+                eligibility: 0..=u64::MAX,
+                reverting_tx_hashes: vec![],
+                replacement_uuid: None,
+                sidecars: Default::default(),
+            }
+        })
+        .collect();
+
+    (client, parent, bundles)
+}
+
+/// drives `job` until it built a payload, and returns the number of transactions in the payload
+async fn built_txs(job: &mut Job<MockEthProvider, NoopTransactionPool>) -> usize {
+    poll_fn(|cx| {
+        let _ = job.poll_unpin(cx);
+        match job.best_blobs_bundle() {
+            Some(_) => Poll::Ready(()),
+            None => Poll::Pending,
+        }
+    })
+    .await;
+    let payload = job.best_payload().expect("job built a payload");
+    payload.block().body.len()
+}
+
+fn build_latency(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("can start runtime");
+    let _guard = runtime.enter();
+
+    let (client, parent, bundles) = chain();
+    let attributes = PayloadBuilderAttributes {
+        id: PayloadId::new([0; 8]),
+        parent: parent.hash,
+        timestamp: parent.timestamp + 12,
+        suggested_fee_recipient: Address::random(),
+        prev_randao: H256::random(),
+        withdrawals: vec![],
+        parent_beacon_block_root: None,
+    };
+
+    let config = BuilderConfig {
+        deadline: Duration::from_secs(12),
+        extra_data: 0,
+        wallet: LocalWallet::new(&mut rand::thread_rng()),
+        algorithms: vec![Arc::new(BundlesFirst)],
+    };
+    let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+    let builder = Builder::new(config, chain, client, NoopTransactionPool::default());
+
+    let (bundle_flow, bundle_requests) = mpsc::unbounded_channel();
+    let (_state_events, events) = mpsc::unbounded_channel();
+    builder.start(bundle_requests, events);
+    for bundle in bundles {
+        bundle_flow
+            .send(BundleRequest::Send(bundle))
+            .expect("builder receives bundles");
+    }
+
+    // wait until the pool holds all of the bundles, so that each job starts from all of them
+    runtime.block_on(async {
+        loop {
+            let mut job = builder
+                .new_payload_job(attributes.clone())
+                .expect("can create job");
+            if built_txs(&mut job).await == NUM_MERGED {
+                break;
+            }
+        }
+    });
+
+    c.bench_function("build payload from 500 bundles", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let mut job = builder
+                    .new_payload_job(attributes.clone())
+                    .expect("can create job");
+                assert_eq!(built_txs(&mut job).await, NUM_MERGED);
+            })
+        })
+    });
+}
+
+criterion_group!(benches, build_latency);
+criterion_main!(benches);
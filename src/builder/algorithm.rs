@@ -0,0 +1,471 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::matches;
+use std::sync::Arc;
+
+use super::blob;
+use crate::bundle::{AccessSet, BundleCompact, BundleId};
+
+use reth_interfaces::Error as RethError;
+use reth_payload_builder::error::PayloadBuilderError;
+use reth_primitives::{TransactionSignedEcRecovered, U256};
+use reth_revm::revm::primitives::{EVMError, InvalidTransaction};
+
+/// a bundle along with the results of its simulation on top of the parent state
+#[derive(Clone, Debug)]
+pub struct SimulatedBundle {
+    pub id: BundleId,
+    pub bundle: BundleCompact,
+    /// the effective coinbase payment per gas
+    pub score: U256,
+    pub access: AccessSet,
+}
+
+/// a payload under construction
+pub trait PendingBlock {
+    /// the base fee of the payload
+    fn base_fee(&self) -> u64;
+
+    /// the gas remaining for the execution of bundles and transactions
+    fn gas_remaining(&self) -> u64;
+
+    /// the blob gas remaining in the payload
+    fn blob_gas_remaining(&self) -> u64;
+
+    /// the price per unit of blob gas in the payload
+    fn blob_gas_price(&self) -> U256;
+
+    /// the state accessed by the bundles and transactions merged so far
+    fn accessed(&self) -> &AccessSet;
+
+    /// executes `bundle` on top of the payload. the bundle is merged into the payload if its
+    /// execution succeeds, if none of its transactions revert without being allowed to revert,
+    /// and if `accept` returns true for the effective coinbase payment per gas of the execution.
+    ///
+    /// returns whether the bundle was merged.
+    fn merge_bundle(
+        &mut self,
+        id: BundleId,
+        bundle: &BundleCompact,
+        accept: &mut dyn FnMut(U256) -> bool,
+    ) -> bool;
+
+    /// executes `tx` on top of the payload
+    fn execute_tx(&mut self, tx: &TransactionSignedEcRecovered) -> Result<(), EVMError<RethError>>;
+}
+
+/// the mempool transactions available to an algorithm, in descending order of priority
+pub trait Mempool {
+    /// returns the next best transaction
+    fn next_tx(&mut self) -> Option<TransactionSignedEcRecovered>;
+
+    /// marks `tx` as invalid, which also removes all transactions that depend on `tx`
+    fn mark_invalid(&mut self, tx: &TransactionSignedEcRecovered);
+}
+
+/// a strategy for ordering and merging bundles and mempool transactions into a payload
+pub trait Algorithm: Debug + Send + Sync {
+    /// the name of the algorithm
+    fn name(&self) -> &'static str;
+
+    /// fills `block` with `bundles` and transactions from `mempool`
+    fn fill(
+        &self,
+        block: &mut dyn PendingBlock,
+        bundles: Vec<SimulatedBundle>,
+        mempool: &mut dyn Mempool,
+    ) -> Result<(), PayloadBuilderError>;
+}
+
+/// the algorithms that a builder runs by default
+pub fn default_algorithms() -> Vec<Arc<dyn Algorithm>> {
+    vec![
+        Arc::new(BundlesFirst),
+        Arc::new(MempoolOnly),
+        Arc::new(Interleaved),
+    ]
+}
+
+/// merges all bundles in order of their effective coinbase payment per gas, then fills the rest
+/// of the payload with mempool transactions
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BundlesFirst;
+
+impl Algorithm for BundlesFirst {
+    fn name(&self) -> &'static str {
+        "bundles-first"
+    }
+
+    fn fill(
+        &self,
+        block: &mut dyn PendingBlock,
+        bundles: Vec<SimulatedBundle>,
+        mempool: &mut dyn Mempool,
+    ) -> Result<(), PayloadBuilderError> {
+        let mut queue = BundleQueue::new(bundles);
+        while queue.merge_next(block) {}
+
+        while let Some(tx) = mempool.next_tx() {
+            merge_tx(block, mempool, tx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// ignores bundles and fills the payload with mempool transactions only
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MempoolOnly;
+
+impl Algorithm for MempoolOnly {
+    fn name(&self) -> &'static str {
+        "mempool-only"
+    }
+
+    fn fill(
+        &self,
+        block: &mut dyn PendingBlock,
+        _bundles: Vec<SimulatedBundle>,
+        mempool: &mut dyn Mempool,
+    ) -> Result<(), PayloadBuilderError> {
+        while let Some(tx) = mempool.next_tx() {
+            merge_tx(block, mempool, tx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// interleaves bundles and mempool transactions, taking whichever of the next best bundle and the
+/// next best transaction pays more per gas. a transaction pays its effective priority fee per gas.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Interleaved;
+
+impl Algorithm for Interleaved {
+    fn name(&self) -> &'static str {
+        "interleaved"
+    }
+
+    fn fill(
+        &self,
+        block: &mut dyn PendingBlock,
+        bundles: Vec<SimulatedBundle>,
+        mempool: &mut dyn Mempool,
+    ) -> Result<(), PayloadBuilderError> {
+        let mut queue = BundleQueue::new(bundles);
+        let mut next_tx = mempool.next_tx();
+        loop {
+            let tx_score = next_tx.as_ref().map(|tx| {
+                let tip = tx
+                    .effective_gas_tip(Some(block.base_fee()))
+                    .unwrap_or_default();
+                U256::from(tip)
+            });
+
+            match (queue.peek_score(), tx_score) {
+                (None, None) => break,
+                (Some(bundle_score), Some(tx_score)) if bundle_score >= tx_score => {
+                    queue.merge_next(block);
+                }
+                (Some(_), None) => {
+                    queue.merge_next(block);
+                }
+                (_, Some(_)) => {
+                    let tx = next_tx.take().expect("transaction has a score");
+                    merge_tx(block, mempool, tx)?;
+                    next_tx = mempool.next_tx();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// simulated bundles in descending order of their effective coinbase payment per gas
+///
+/// a bundle whose simulated state access conflicts with the state accessed by the payload is
+/// re-simulated on top of the payload. if its payment per gas drops below that of the next best
+/// bundle, then the bundle is requeued with its updated score. each bundle is requeued at most
+/// once.
+struct BundleQueue {
+    bundles: HashMap<BundleId, SimulatedBundle>,
+    queue: BinaryHeap<(U256, Reverse<BundleId>)>,
+    requeued: HashSet<BundleId>,
+}
+
+impl BundleQueue {
+    fn new(bundles: Vec<SimulatedBundle>) -> Self {
+        let queue = bundles
+            .iter()
+            .map(|bundle| (bundle.score, Reverse(bundle.id)))
+            .collect();
+        let bundles = bundles
+            .into_iter()
+            .map(|bundle| (bundle.id, bundle))
+            .collect();
+
+        Self {
+            bundles,
+            queue,
+            requeued: HashSet::new(),
+        }
+    }
+
+    /// returns the score of the next best bundle
+    fn peek_score(&self) -> Option<U256> {
+        self.queue.peek().map(|(score, _)| *score)
+    }
+
+    /// attempts to merge the next best bundle into `block`
+    ///
+    /// returns false if there are no more bundles to merge.
+    fn merge_next(&mut self, block: &mut dyn PendingBlock) -> bool {
+        let Some((_, Reverse(id))) = self.queue.pop() else {
+            return false;
+        };
+        let SimulatedBundle { bundle, access, .. } = &self.bundles[&id];
+
+        // if the bundle does not conflict with the payload, then its execution matches the
+        // simulation
+        if !access.conflicts(block.accessed()) || self.requeued.contains(&id) {
+            block.merge_bundle(id, bundle, &mut |_| true);
+            return true;
+        }
+
+        let next_score = self.queue.peek().map(|(score, _)| *score);
+        let mut requeue_score = None;
+        let merged = block.merge_bundle(id, bundle, &mut |score| {
+            if next_score.is_some_and(|next| score < next) {
+                requeue_score = Some(score);
+                return false;
+            }
+            true
+        });
+        if let (false, Some(score)) = (merged, requeue_score) {
+            self.requeued.insert(id);
+            self.queue.push((score, Reverse(id)));
+        }
+
+        true
+    }
+}
+
+/// attempts to execute mempool transaction `tx` on top of `block`
+fn merge_tx(
+    block: &mut dyn PendingBlock,
+    mempool: &mut dyn Mempool,
+    tx: TransactionSignedEcRecovered,
+) -> Result<(), PayloadBuilderError> {
+    // if we don't have sufficient gas for the transaction, then we skip past it. we also mark the
+    // transaction invalid, which will remove any subsequent transactions that depend on it from
+    // the mempool.
+    if tx.gas_limit() > block.gas_remaining() {
+        mempool.mark_invalid(&tx);
+        return Ok(());
+    }
+
+    // likewise for blob transactions that exceed the blob gas remaining or that do not pay the
+    // blob gas price
+    if blob::blob_gas(&tx) > block.blob_gas_remaining()
+        || !blob::pays_blob_gas_price(&tx, block.blob_gas_price())
+    {
+        mempool.mark_invalid(&tx);
+        return Ok(());
+    }
+
+    match block.execute_tx(&tx) {
+        Ok(()) => Ok(()),
+        // if we have any transaction error other than the nonce being too low, then we mark the
+        // transaction invalid
+        Err(EVMError::Transaction(err)) => {
+            if !matches!(err, InvalidTransaction::NonceTooLow { .. }) {
+                mempool.mark_invalid(&tx);
+            }
+            Ok(())
+        }
+        // treat any other errors as fatal
+        Err(err) => Err(PayloadBuilderError::EvmExecutionError(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reth_primitives::{Address, Signature, Transaction, TransactionSigned, TxEip1559};
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Merged {
+        Bundle(BundleId),
+        Tx(u64),
+    }
+
+    /// a pending block in which each bundle pays a fixed amount per gas
+    #[derive(Default)]
+    struct MockBlock {
+        scores: HashMap<BundleId, U256>,
+        access: HashMap<BundleId, AccessSet>,
+        accessed: AccessSet,
+        merged: Vec<Merged>,
+    }
+
+    impl PendingBlock for MockBlock {
+        fn base_fee(&self) -> u64 {
+            0
+        }
+
+        fn gas_remaining(&self) -> u64 {
+            u64::MAX
+        }
+
+        fn blob_gas_remaining(&self) -> u64 {
+            u64::MAX
+        }
+
+        fn blob_gas_price(&self) -> U256 {
+            U256::from(1)
+        }
+
+        fn accessed(&self) -> &AccessSet {
+            &self.accessed
+        }
+
+        fn merge_bundle(
+            &mut self,
+            id: BundleId,
+            _bundle: &BundleCompact,
+            accept: &mut dyn FnMut(U256) -> bool,
+        ) -> bool {
+            if !accept(self.scores[&id]) {
+                return false;
+            }
+            self.accessed.extend(&self.access[&id]);
+            self.merged.push(Merged::Bundle(id));
+            true
+        }
+
+        fn execute_tx(
+            &mut self,
+            tx: &TransactionSignedEcRecovered,
+        ) -> Result<(), EVMError<RethError>> {
+            self.merged.push(Merged::Tx(tx.nonce()));
+            Ok(())
+        }
+    }
+
+    impl MockBlock {
+        /// adds a bundle that pays `score` per gas on top of the block, and that pays
+        /// `simulated_score` per gas in simulation
+        fn add_bundle(
+            &mut self,
+            id: BundleId,
+            simulated_score: u64,
+            score: u64,
+            access: AccessSet,
+        ) -> SimulatedBundle {
+            self.scores.insert(id, U256::from(score));
+            self.access.insert(id, access.clone());
+            SimulatedBundle {
+                id,
+                bundle: BundleCompact {
+                    txs: vec![],
+                    reverting_tx_hashes: vec![],
+                    sidecars: Default::default(),
+                },
+                score: U256::from(simulated_score),
+                access,
+            }
+        }
+    }
+
+    struct MockMempool(Vec<TransactionSignedEcRecovered>);
+
+    impl Mempool for MockMempool {
+        fn next_tx(&mut self) -> Option<TransactionSignedEcRecovered> {
+            (!self.0.is_empty()).then(|| self.0.remove(0))
+        }
+
+        fn mark_invalid(&mut self, _tx: &TransactionSignedEcRecovered) {}
+    }
+
+    fn tx(nonce: u64, priority_fee: u128) -> TransactionSignedEcRecovered {
+        let tx = Transaction::Eip1559(TxEip1559 {
+            nonce,
+            gas_limit: 21000,
+            max_fee_per_gas: priority_fee,
+            max_priority_fee_per_gas: priority_fee,
+            ..Default::default()
+        });
+        let tx = TransactionSigned::from_transaction_and_signature(tx, Signature::default());
+        TransactionSignedEcRecovered::from_signed_transaction(tx, Address::random())
+    }
+
+    fn access(account: Address, written: bool) -> AccessSet {
+        let mut access = AccessSet::default();
+        access.record_account(account, written);
+        access
+    }
+
+    #[test]
+    fn bundles_first_requeues_conflicting_bundle() {
+        let account = Address::random();
+        let mut block = MockBlock::default();
+        let bundles = vec![
+            block.add_bundle(0, 30, 30, access(account, true)),
+            // conflicts with the first bundle, and pays less once re-simulated
+            block.add_bundle(1, 20, 5, access(account, false)),
+            block.add_bundle(2, 10, 10, access(Address::random(), true)),
+        ];
+        let mut mempool = MockMempool(vec![tx(0, 100)]);
+
+        BundlesFirst
+            .fill(&mut block, bundles, &mut mempool)
+            .expect("fill doesn't fail");
+        assert_eq!(
+            block.merged,
+            vec![
+                Merged::Bundle(0),
+                Merged::Bundle(2),
+                Merged::Bundle(1),
+                Merged::Tx(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn mempool_only_ignores_bundles() {
+        let mut block = MockBlock::default();
+        let bundles = vec![block.add_bundle(0, 30, 30, AccessSet::default())];
+        let mut mempool = MockMempool(vec![tx(0, 1), tx(1, 1)]);
+
+        MempoolOnly
+            .fill(&mut block, bundles, &mut mempool)
+            .expect("fill doesn't fail");
+        assert_eq!(block.merged, vec![Merged::Tx(0), Merged::Tx(1)]);
+    }
+
+    #[test]
+    fn interleaved_orders_by_payment_per_gas() {
+        let mut block = MockBlock::default();
+        let bundles = vec![
+            block.add_bundle(0, 20, 20, access(Address::random(), true)),
+            block.add_bundle(1, 5, 5, access(Address::random(), true)),
+        ];
+        let mut mempool = MockMempool(vec![tx(0, 10), tx(1, 1)]);
+
+        Interleaved
+            .fill(&mut block, bundles, &mut mempool)
+            .expect("fill doesn't fail");
+        assert_eq!(
+            block.merged,
+            vec![
+                Merged::Bundle(0),
+                Merged::Tx(0),
+                Merged::Bundle(1),
+                Merged::Tx(1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use reth_primitives::{Transaction, TransactionSigned, U256};
+
+/// the blob gas consumed by a single blob
+pub const BLOB_GAS_PER_BLOB: u64 = 1 << 17;
+/// the target amount of blob gas consumed per block
+pub const TARGET_BLOB_GAS_PER_BLOCK: u64 = 3 * BLOB_GAS_PER_BLOB;
+/// the maximum amount of blob gas consumed per block
+pub const MAX_BLOB_GAS_PER_BLOCK: u64 = 6 * BLOB_GAS_PER_BLOB;
+
+const MIN_BLOB_GASPRICE: u64 = 1;
+const BLOB_GASPRICE_UPDATE_FRACTION: u64 = 3338477;
+
+/// returns the excess blob gas of a block whose parent has `parent_excess_blob_gas` and
+/// `parent_blob_gas_used`
+pub fn calc_excess_blob_gas(parent_excess_blob_gas: u64, parent_blob_gas_used: u64) -> u64 {
+    (parent_excess_blob_gas + parent_blob_gas_used).saturating_sub(TARGET_BLOB_GAS_PER_BLOCK)
+}
+
+/// returns the price per unit of blob gas for a block with `excess_blob_gas`
+pub fn blob_gas_price(excess_blob_gas: u64) -> U256 {
+    fake_exponential(
+        U256::from(MIN_BLOB_GASPRICE),
+        U256::from(excess_blob_gas),
+        U256::from(BLOB_GASPRICE_UPDATE_FRACTION),
+    )
+}
+
+/// approximates `factor * e ** (numerator / denominator)` using a taylor expansion
+///
+/// see https://eips.ethereum.org/EIPS/eip-4844#helpers
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut i = U256::from(1);
+    let mut output = U256::ZERO;
+    let mut numerator_accum = factor * denominator;
+    while numerator_accum > U256::ZERO {
+        output = output.saturating_add(numerator_accum);
+        numerator_accum = numerator_accum.saturating_mul(numerator) / (denominator * i);
+        i += U256::from(1);
+    }
+    output / denominator
+}
+
+/// returns the blob gas consumed by `tx`
+pub fn blob_gas(tx: &TransactionSigned) -> u64 {
+    match &tx.transaction {
+        Transaction::Eip4844(tx) => tx.blob_versioned_hashes.len() as u64 * BLOB_GAS_PER_BLOB,
+        _ => 0,
+    }
+}
+
+/// returns the most that `tx` is willing to pay for its blob gas
+pub fn max_blob_fee(tx: &TransactionSigned) -> U256 {
+    match &tx.transaction {
+        Transaction::Eip4844(tx) => {
+            U256::from(tx.blob_versioned_hashes.len() as u64 * BLOB_GAS_PER_BLOB)
+                * U256::from(tx.max_fee_per_blob_gas)
+        }
+        _ => U256::ZERO,
+    }
+}
+
+/// returns whether `tx` is willing to pay `blob_gas_price` per unit of blob gas
+pub fn pays_blob_gas_price(tx: &TransactionSigned, blob_gas_price: U256) -> bool {
+    match &tx.transaction {
+        Transaction::Eip4844(tx) => U256::from(tx.max_fee_per_blob_gas) >= blob_gas_price,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excess_blob_gas() {
+        assert_eq!(calc_excess_blob_gas(0, 0), 0);
+        assert_eq!(calc_excess_blob_gas(0, TARGET_BLOB_GAS_PER_BLOCK), 0);
+        assert_eq!(
+            calc_excess_blob_gas(0, MAX_BLOB_GAS_PER_BLOCK),
+            MAX_BLOB_GAS_PER_BLOCK - TARGET_BLOB_GAS_PER_BLOCK
+        );
+        assert_eq!(
+            calc_excess_blob_gas(BLOB_GAS_PER_BLOB, 0),
+            BLOB_GAS_PER_BLOB.saturating_sub(TARGET_BLOB_GAS_PER_BLOCK)
+        );
+        assert_eq!(
+            calc_excess_blob_gas(TARGET_BLOB_GAS_PER_BLOCK, MAX_BLOB_GAS_PER_BLOCK),
+            MAX_BLOB_GAS_PER_BLOCK
+        );
+    }
+
+    #[test]
+    fn blob_gas_price_follows_excess() {
+        assert_eq!(blob_gas_price(0), U256::from(1));
+        assert_eq!(blob_gas_price(TARGET_BLOB_GAS_PER_BLOCK), U256::from(1));
+        assert_eq!(blob_gas_price(BLOB_GASPRICE_UPDATE_FRACTION), U256::from(2));
+        assert_eq!(
+            blob_gas_price(10 * BLOB_GASPRICE_UPDATE_FRACTION),
+            U256::from(22026)
+        );
+    }
+}
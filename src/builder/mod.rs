@@ -0,0 +1,2688 @@
+use std::collections::{hash_map, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bundle::{pool::BundlePool, AccessSet, Bundle, BundleCompact, BundleId, BundleRequest};
+use crate::types::BlobsBundle;
+
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::{
+            eip1559::Eip1559TransactionRequest, eip2718::TypedTransaction, eip2930::AccessList,
+        },
+        Bytes as EthersBytes, NameOrAddress,
+    },
+};
+use futures_util::{stream::Fuse, FutureExt, Stream, StreamExt};
+use reth_interfaces::Error as RethError;
+use reth_payload_builder::{
+    error::PayloadBuilderError, BuiltPayload, KeepPayloadJobAlive, PayloadBuilderAttributes,
+    PayloadJob, PayloadJobGenerator,
+};
+use reth_primitives::{
+    constants::{BEACON_NONCE, EMPTY_OMMER_ROOT},
+    proofs, BlobTransactionSidecar, Block, Bytes, ChainSpec, Hardfork, Header,
+    IntoRecoveredTransaction, Receipt, SealedHeader, TransactionSigned,
+    TransactionSignedEcRecovered, TxHash, H256, U256,
+};
+use reth_provider::{
+    BlockReaderIdExt, CanonStateNotification, PostState, StateProvider, StateProviderFactory,
+};
+use reth_revm::{
+    database::State,
+    env::tx_env_with_recovered,
+    executor::{
+        commit_state_changes, increment_account_balance, post_block_withdrawals_balance_increments,
+    },
+    into_reth_acc, into_reth_log,
+    revm::{
+        db::{AccountState, CacheDB, DatabaseRef},
+        primitives::{
+            Account, AccountInfo, BlockEnv, CfgEnv, EVMError, Env, InvalidTransaction,
+            ResultAndState, TransactTo, TxEnv, B160, KECCAK_EMPTY,
+        },
+        EVM,
+    },
+};
+use reth_transaction_pool::{
+    noop::NoopTransactionPool, BestTransactions, TransactionPool, ValidPoolTransaction,
+};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    task,
+    time::{sleep, Sleep},
+};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_util::time::DelayQueue;
+use uuid::Uuid;
+
+pub mod algorithm;
+pub mod blob;
+
+use algorithm::{Algorithm, BundlesFirst, Mempool, PendingBlock, SimulatedBundle};
+
+struct UnpackagedPayload<S: StateProvider> {
+    attributes: PayloadBuilderAttributes,
+    block_env: BlockEnv,
+    state: Arc<State<S>>,
+    post_state: PostState,
+    extra_data: u128,
+    txs: Vec<TransactionSigned>,
+    bundles: HashSet<BundleId>,
+    cumulative_gas_used: u64,
+    blob_gas_used: Option<u64>,
+    excess_blob_gas: Option<u64>,
+    parent_beacon_block_root: Option<H256>,
+    blobs_bundle: BlobsBundle,
+    proposer_payment: U256,
+    algorithm: &'static str,
+}
+
+impl<S: StateProvider> UnpackagedPayload<S> {
+    pub fn package(self) -> Result<Payload, PayloadBuilderError> {
+        let base_fee = self.block_env.basefee.to::<u64>();
+        let block_num = self.block_env.number.to::<u64>();
+        let block_gas_limit: u64 = self.block_env.gas_limit.try_into().unwrap_or(u64::MAX);
+
+        // compute accumulators
+        let receipts_root = self.post_state.receipts_root(block_num);
+        let logs_bloom = self.post_state.logs_bloom(block_num);
+        let transactions_root = proofs::calculate_transaction_root(&self.txs);
+        let withdrawals_root = proofs::calculate_withdrawals_root(&self.attributes.withdrawals);
+        let state_root = self.state.state().state_root(self.post_state)?;
+
+        let header = Header {
+            parent_hash: self.attributes.parent,
+            ommers_hash: EMPTY_OMMER_ROOT,
+            beneficiary: self.block_env.coinbase,
+            state_root,
+            transactions_root,
+            receipts_root,
+            withdrawals_root: Some(withdrawals_root),
+            logs_bloom,
+            difficulty: U256::ZERO,
+            number: block_num,
+            gas_limit: block_gas_limit,
+            gas_used: self.cumulative_gas_used,
+            timestamp: self.attributes.timestamp,
+            mix_hash: self.attributes.prev_randao,
+            nonce: BEACON_NONCE,
+            base_fee_per_gas: Some(base_fee),
+            blob_gas_used: self.blob_gas_used,
+            excess_blob_gas: self.excess_blob_gas,
+            parent_beacon_block_root: self.parent_beacon_block_root,
+            extra_data: self.extra_data.to_le_bytes().into(),
+        };
+
+        let block = Block {
+            header,
+            body: self.txs,
+            ommers: vec![],
+            withdrawals: Some(self.attributes.withdrawals.clone()),
+        };
+        let block = block.seal_slow();
+
+        let payload = BuiltPayload::new(self.attributes.id, block, self.proposer_payment);
+        let payload = Payload {
+            inner: Arc::new(payload),
+            bundles: self.bundles,
+            blobs_bundle: self.blobs_bundle,
+            proposer_payment: self.proposer_payment,
+            algorithm: self.algorithm,
+        };
+
+        Ok(payload)
+    }
+}
+
+struct Payload {
+    inner: Arc<BuiltPayload>,
+    bundles: HashSet<BundleId>,
+    /// the blobs of the blob transactions in the payload, in order of inclusion
+    blobs_bundle: BlobsBundle,
+    /// the value of the payload to the proposer
+    proposer_payment: U256,
+    /// the name of the algorithm that built the payload
+    algorithm: &'static str,
+}
+
+#[derive(Clone, Debug)]
+struct PayloadAttributes {
+    inner: PayloadBuilderAttributes,
+    extra_data: u128,
+    wallet: LocalWallet,
+}
+
+#[derive(Clone, Debug)]
+struct JobConfig {
+    attributes: PayloadAttributes,
+    parent: Arc<SealedHeader>,
+    chain: Arc<ChainSpec>,
+    algorithms: Vec<Arc<dyn Algorithm>>,
+}
+
+/// a build job scoped to `config`
+pub struct Job<Client, Pool> {
+    config: JobConfig,
+    deadline: Pin<Box<Sleep>>,
+    client: Arc<Client>,
+    pool: Arc<Pool>,
+    bundle_pool: Arc<Mutex<BundlePool>>,
+    bundles: HashMap<BundleId, BundleCompact>,
+    replacements: HashMap<Uuid, BundleId>,
+    unsimulated: Vec<BundleId>,
+    simulations: HashMap<BundleId, Execution>,
+    incoming: Fuse<BroadcastStream<Bundle>>,
+    invalidated: Fuse<BroadcastStream<BundleId>>,
+    /// the most valuable payload that each algorithm built, keyed by the name of the algorithm
+    built_payloads: HashMap<&'static str, Payload>,
+    pending_simulations: VecDeque<task::JoinHandle<Result<Simulations, PayloadBuilderError>>>,
+    pending_payloads: VecDeque<task::JoinHandle<Result<Payload, PayloadBuilderError>>>,
+}
+
+impl<Client, Pool> Job<Client, Pool> {
+    /// a job that starts from the eligible bundles in `bundle_pool`, and follows the bundle
+    /// broadcasts from there on
+    ///
+    /// NOTE: the job must subscribe to `incoming` and `invalidated` before it is created, so that
+    /// it does not miss a bundle that enters or leaves the pool in between.
+    fn new(
+        config: JobConfig,
+        deadline: Pin<Box<Sleep>>,
+        client: Arc<Client>,
+        pool: Arc<Pool>,
+        bundle_pool: Arc<Mutex<BundlePool>>,
+        incoming: Fuse<BroadcastStream<Bundle>>,
+        invalidated: Fuse<BroadcastStream<BundleId>>,
+    ) -> Self {
+        let mut job = Self {
+            config,
+            deadline,
+            client,
+            pool,
+            bundle_pool,
+            bundles: HashMap::new(),
+            replacements: HashMap::new(),
+            unsimulated: Vec::new(),
+            simulations: HashMap::new(),
+            invalidated,
+            incoming,
+            built_payloads: HashMap::new(),
+            pending_simulations: VecDeque::new(),
+            pending_payloads: VecDeque::new(),
+        };
+        job.resync();
+        job
+    }
+
+    /// syncs the bundles of the job with the eligible bundles in the bundle pool. the job falls
+    /// back on the pool whenever it lags behind the bundle broadcasts, since it may have missed a
+    /// replacement or an invalidation.
+    ///
+    /// returns the IDs of the bundles that the job dropped.
+    ///
+    /// NOTE: a bundle that the job dropped because its simulation failed is still in the pool, so
+    /// the job simulates it again.
+    fn resync(&mut self) -> HashSet<BundleId> {
+        let block_num = self.config.parent.number + 1;
+        let timestamp = self.config.attributes.inner.timestamp;
+        let eligible = self
+            .bundle_pool
+            .lock()
+            .unwrap()
+            .eligible(block_num, timestamp);
+
+        let live: HashSet<_> = eligible.iter().map(|bundle| bundle.id).collect();
+        let dropped: HashSet<_> = self
+            .bundles
+            .keys()
+            .filter(|id| !live.contains(id))
+            .copied()
+            .collect();
+        for id in &dropped {
+            self.bundles.remove(id);
+            self.simulations.remove(id);
+        }
+
+        self.replacements.clear();
+        for bundle in eligible {
+            if let Some(uuid) = bundle.replacement_uuid {
+                self.replacements.insert(uuid, bundle.id);
+            }
+            if !self.bundles.contains_key(&bundle.id) {
+                self.unsimulated.push(bundle.id);
+                self.bundles.insert(bundle.id, BundleCompact::from(bundle));
+            }
+        }
+
+        dropped
+    }
+
+    /// caches `payload` if it is the most valuable payload of its algorithm so far
+    ///
+    /// NOTE: a payload that contains an expired bundle is dropped along with the bundle. the
+    /// expiration triggers a new build of each algorithm, which takes the place of the payload.
+    fn cache(&mut self, payload: Payload) {
+        match self.built_payloads.entry(payload.algorithm) {
+            hash_map::Entry::Occupied(mut best) => {
+                if payload.proposer_payment >= best.get().proposer_payment {
+                    best.insert(payload);
+                }
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(payload);
+            }
+        }
+    }
+
+    /// returns the built payload with the highest value to the proposer
+    fn best(&self) -> Option<&Payload> {
+        self.built_payloads
+            .values()
+            .max_by_key(|payload| payload.proposer_payment)
+    }
+
+    /// returns the blobs bundle of the built payload with the highest value to the proposer
+    pub fn best_blobs_bundle(&self) -> Option<BlobsBundle> {
+        self.best().map(|payload| payload.blobs_bundle.clone())
+    }
+}
+
+impl<Client, Pool> Future for Job<Client, Pool>
+where
+    Client: StateProviderFactory + 'static,
+    Pool: TransactionPool + 'static,
+{
+    type Output = Result<(), PayloadBuilderError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let config = self.config.clone();
+
+        let this = self.get_mut();
+
+        // check whether the deadline for the job expired
+        if this.deadline.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Ok(()));
+        }
+
+        // incorporate new incoming bundles
+        let mut expired_bundles = HashSet::new();
+        let mut lagged = false;
+        let mut incoming = Pin::new(&mut this.incoming);
+        loop {
+            match incoming.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(bundle))) => {
+                    // if the bundle replaces a previous version, then drop the previous version.
+                    // we do this before the eligibility check, so that a replacement that is not
+                    // eligible for the job still withdraws the previous version.
+                    if let Some(uuid) = bundle.replacement_uuid {
+                        if let Some(replaced) = this.replacements.insert(uuid, bundle.id) {
+                            this.bundles.remove(&replaced);
+                            this.simulations.remove(&replaced);
+                            expired_bundles.insert(replaced);
+                        }
+                    }
+
+                    // if the bundle is not eligible for the job, then skip the bundle
+                    let block_num = this.config.parent.number + 1;
+                    let timestamp = this.config.attributes.inner.timestamp;
+                    if !bundle.block_range.contains(&block_num)
+                        || !bundle.eligibility.contains(&timestamp)
+                    {
+                        continue;
+                    }
+
+                    this.unsimulated.push(bundle.id);
+                    this.bundles.insert(bundle.id, BundleCompact::from(bundle));
+                }
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(_skipped)))) => {
+                    lagged = true;
+                }
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        // remove any invalidated bundles
+        let mut invalidated = Pin::new(&mut this.invalidated);
+        loop {
+            match invalidated.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(exp))) => {
+                    this.bundles.remove(&exp);
+                    this.simulations.remove(&exp);
+                    this.replacements.retain(|_, id| *id != exp);
+                    expired_bundles.insert(exp);
+                }
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(_skipped)))) => {
+                    lagged = true;
+                }
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        // if the job missed any broadcasts, then it may still hold a replaced or invalidated
+        // bundle, so we fall back on the bundle pool
+        if lagged {
+            expired_bundles.extend(this.resync());
+        }
+
+        // remove all payloads that contain an expired bundle
+        this.built_payloads
+            .retain(|_, payload| payload.bundles.is_disjoint(&expired_bundles));
+
+        // simulate new bundles against the parent state to determine the state they access
+        if !this.unsimulated.is_empty() {
+            let bundles = this
+                .unsimulated
+                .drain(..)
+                .filter_map(|id| this.bundles.get(&id).map(|bundle| (id, bundle.clone())))
+                .collect();
+
+            let config = config.clone();
+            let client = Arc::clone(&this.client);
+            let pending = task::spawn_blocking(move || simulate(config, client, bundles));
+
+            this.pending_simulations.push_back(pending);
+        }
+
+        // poll all pending simulations
+        let mut num_simulated_bundles = 0;
+        for mut pending in std::mem::take(&mut this.pending_simulations) {
+            match pending.poll_unpin(cx) {
+                Poll::Ready(Ok(Ok(simulations))) => {
+                    for (id, simulation) in simulations {
+                        match simulation {
+                            // the bundle may have been invalidated during the simulation
+                            Some(execution) if this.bundles.contains_key(&id) => {
+                                this.simulations.insert(id, execution);
+                                num_simulated_bundles += 1;
+                            }
+                            // a bundle that is invalid on the parent state is never included
+                            _ => {
+                                this.bundles.remove(&id);
+                            }
+                        }
+                    }
+                }
+                Poll::Ready(Ok(Err(..))) => {
+                    // simulation task failed
+                }
+                Poll::Ready(Err(..)) => {
+                    // `recv` failed
+                }
+                Poll::Pending => this.pending_simulations.push_back(pending),
+            }
+        }
+
+        // if there are any expired or newly simulated bundles, then build a new payload
+        if !expired_bundles.is_empty() || num_simulated_bundles > 0 {
+            // NOTE: the simulations allow the algorithms to order the bundles by their effective
+            // coinbase payment per gas. see `algorithm::BundleQueue`.
+            let bundles: Vec<SimulatedBundle> = this
+                .simulations
+                .iter()
+                .filter_map(|(id, simulation)| {
+                    let bundle = this.bundles.get(id)?.clone();
+                    Some(SimulatedBundle::new(*id, bundle, simulation))
+                })
+                .collect();
+
+            // run each of the algorithms on a separate task
+            for algorithm in &config.algorithms {
+                let config = config.clone();
+                let client = Arc::clone(&this.client);
+                let pool = Arc::clone(&this.pool);
+                let bundles = bundles.clone();
+                let algorithm = Arc::clone(algorithm);
+                let pending = task::spawn_blocking(move || {
+                    build(config, client, pool, bundles, algorithm.as_ref())
+                });
+
+                this.pending_payloads.push_back(pending);
+            }
+        }
+
+        // poll all pending payloads
+        for mut pending in std::mem::take(&mut this.pending_payloads) {
+            match pending.poll_unpin(cx) {
+                Poll::Ready(payload) => {
+                    match payload {
+                        Ok(Ok(payload)) => {
+                            tracing::debug!(
+                                algorithm = payload.algorithm,
+                                proposer_payment = %payload.proposer_payment,
+                                "built payload"
+                            );
+
+                            this.cache(payload);
+                        }
+                        Ok(Err(..)) => {
+                            // build task failed
+                        }
+                        Err(..) => {
+                            // `recv` failed
+                        }
+                    }
+                }
+                Poll::Pending => this.pending_payloads.push_back(pending),
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+pub struct PayloadTask {
+    best_payload: Option<Arc<BuiltPayload>>,
+    empty_payload: Option<oneshot::Receiver<Result<Payload, PayloadBuilderError>>>,
+}
+
+impl Future for PayloadTask {
+    type Output = Result<Arc<BuiltPayload>, PayloadBuilderError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Some(best) = this.best_payload.take() {
+            return Poll::Ready(Ok(best));
+        }
+
+        let mut empty = this.empty_payload.take().unwrap();
+        match empty.poll_unpin(cx) {
+            Poll::Ready(Ok(res)) => Poll::Ready(res.map(|p| p.inner)),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err.into())),
+            Poll::Pending => {
+                this.empty_payload = Some(empty);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<Client, Pool> PayloadJob for Job<Client, Pool>
+where
+    Client: StateProviderFactory + Send + Sync + 'static,
+    Pool: TransactionPool + 'static,
+{
+    type ResolvePayloadFuture = PayloadTask;
+
+    fn best_payload(&self) -> Result<Arc<BuiltPayload>, PayloadBuilderError> {
+        if let Some(best) = self.best() {
+            return Ok(Arc::clone(&best.inner));
+        }
+
+        let empty = build(
+            self.config.clone(),
+            Arc::clone(&self.client),
+            Arc::new(NoopTransactionPool::default()),
+            None,
+            &BundlesFirst,
+        )?;
+        Ok(empty.inner)
+    }
+
+    fn resolve(&mut self) -> (Self::ResolvePayloadFuture, KeepPayloadJobAlive) {
+        let best_payload = self.best().map(|p| p.inner.clone());
+
+        // if there is no best payload, then build an empty payload
+        let empty_payload = if best_payload.is_none() {
+            let (tx, rx) = oneshot::channel();
+            let config = self.config.clone();
+            let client = Arc::clone(&self.client);
+            let pool = Arc::new(NoopTransactionPool::default());
+            task::spawn_blocking(move || {
+                let payload = build(config, client, pool, None, &BundlesFirst);
+                let _ = tx.send(payload);
+            });
+
+            Some(rx)
+        } else {
+            None
+        };
+
+        (
+            PayloadTask {
+                best_payload,
+                empty_payload,
+            },
+            KeepPayloadJobAlive::No,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BuilderConfig {
+    pub deadline: Duration,
+    pub extra_data: u128,
+    pub wallet: LocalWallet,
+    /// the algorithms that each job runs in parallel. see [`algorithm::default_algorithms`].
+    pub algorithms: Vec<Arc<dyn Algorithm>>,
+}
+
+/// the longest timeout that the builder tracks in its expiration queue. bundles that expire
+/// further out are left to [`BundlePool::tick`].
+///
+/// NOTE: `DelayQueue` panics on timeouts of more than roughly two years
+const MAX_EXPIRATION_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct Builder<Client, Pool> {
+    chain: Arc<ChainSpec>,
+    deadline: Duration,
+    wallet: LocalWallet,
+    extra_data: u128,
+    algorithms: Vec<Arc<dyn Algorithm>>,
+    client: Arc<Client>,
+    pool: Arc<Pool>,
+    bundle_pool: Arc<Mutex<BundlePool>>,
+    incoming: broadcast::Sender<Bundle>,
+    invalidated: broadcast::Sender<BundleId>,
+}
+
+impl<Client, Pool> Builder<Client, Pool>
+where
+    Client: StateProviderFactory + Unpin,
+    Pool: TransactionPool + Unpin,
+{
+    pub fn new(config: BuilderConfig, chain: ChainSpec, client: Client, pool: Pool) -> Self {
+        let chain = Arc::new(chain);
+        let client = Arc::new(client);
+        let pool = Arc::new(pool);
+        let (incoming, _) = broadcast::channel(256);
+        let (invalidated, _) = broadcast::channel(256);
+
+        let bundle_pool = BundlePool::default();
+        let bundle_pool = Arc::new(Mutex::new(bundle_pool));
+
+        Self {
+            chain,
+            deadline: config.deadline,
+            wallet: config.wallet,
+            extra_data: config.extra_data,
+            algorithms: config.algorithms,
+            client,
+            pool,
+            bundle_pool,
+            incoming,
+            invalidated,
+        }
+    }
+
+    /// spawns the builder maintenance task
+    pub fn start(
+        &self,
+        mut bundle_flow: mpsc::UnboundedReceiver<BundleRequest>,
+        mut state_events: mpsc::UnboundedReceiver<CanonStateNotification>,
+    ) {
+        let bundle_pool = Arc::clone(&self.bundle_pool);
+        let invalidated = self.invalidated.clone();
+        let incoming = self.incoming.clone();
+
+        tokio::spawn(async move {
+            // bundle refresh interval
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+            // track bundle expirations. we keep the keys into the queue, so that bundles removed
+            // from the pool for other reasons (e.g. their block range passed) stop being tracked.
+            let mut bundle_expirations = DelayQueue::new();
+            let mut expiration_keys = HashMap::new();
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        bundle_pool.lock().unwrap().tick(SystemTime::now());
+                    }
+                    Some(request) = bundle_flow.recv() => {
+                        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+                        // if the bundle already expired, then ignore it. the bundle still
+                        // supersedes any earlier bundle with the same UUID, so we withdraw that
+                        // one as if it were cancelled.
+                        //
+                        // NOTE: eligibility is evaluated against payload timestamps. a payload is
+                        // never built for a timestamp in the past, so once the current time passes
+                        // the end of the eligibility window, the bundle can never be included.
+                        let request = match request {
+                            BundleRequest::Send(bundle) if *bundle.eligibility.end() < now => {
+                                match bundle.replacement_uuid {
+                                    Some(uuid) => BundleRequest::Cancel(uuid),
+                                    None => continue,
+                                }
+                            }
+                            request => request,
+                        };
+
+                        match request {
+                            BundleRequest::Send(bundle) => {
+                                // track the timeout of the bundle. bundles without an upper bound
+                                // on their eligibility never expire by time, and the pool drops
+                                // the bundles that expire beyond the cap.
+                                let timeout = bundle
+                                    .eligibility
+                                    .end()
+                                    .saturating_sub(now)
+                                    .saturating_add(1);
+                                let timeout = Duration::from_secs(timeout);
+                                if timeout <= MAX_EXPIRATION_TIMEOUT {
+                                    let key = bundle_expirations.insert(bundle.id, timeout);
+                                    expiration_keys.insert(bundle.id, key);
+                                }
+
+                                // NOTE: jobs drop any replaced bundle upon receipt of the
+                                // replacement, so we do not need to notify them about the replaced
+                                // bundle
+                                let replaced = bundle_pool.lock().unwrap().insert(bundle.clone());
+                                if let Some(key) =
+                                    replaced.and_then(|id| expiration_keys.remove(&id))
+                                {
+                                    bundle_expirations.remove(&key);
+                                }
+
+                                // notify jobs about new bundle
+                                //
+                                // NOTE: you could create metadata (e.g. access list) about the
+                                // bundle here or within each job
+                                let _ = incoming.send(bundle);
+                            }
+                            BundleRequest::Cancel(uuid) => {
+                                // notify jobs about cancelled bundle
+                                let cancelled = bundle_pool.lock().unwrap().cancel(uuid);
+                                if let Some(cancelled) = cancelled {
+                                    if let Some(key) = expiration_keys.remove(&cancelled) {
+                                        bundle_expirations.remove(&key);
+                                    }
+                                    let _ = invalidated.send(cancelled);
+                                }
+                            }
+                        }
+                    }
+                    Some(expired) = bundle_expirations.next() => {
+                        // notify jobs about expired bundle
+                        let expired = expired.into_inner();
+                        expiration_keys.remove(&expired);
+                        let _ = invalidated.send(expired);
+                    }
+                    Some(event) = state_events.recv() => {
+                        // maintain the bundle pool based on state events. notify jobs about
+                        // invalidated bundles.
+                        let removed = bundle_pool.lock().unwrap().maintain(event);
+                        for bundle in removed {
+                            if let Some(key) = expiration_keys.remove(&bundle) {
+                                bundle_expirations.remove(&key);
+                            }
+                            let _ = invalidated.send(bundle);
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl<Client, Pool> PayloadJobGenerator for Builder<Client, Pool>
+where
+    Client: StateProviderFactory + BlockReaderIdExt + 'static,
+    Pool: TransactionPool + 'static,
+{
+    type Job = Job<Client, Pool>;
+
+    fn new_payload_job(
+        &self,
+        attributes: PayloadBuilderAttributes,
+    ) -> Result<Self::Job, PayloadBuilderError> {
+        // retrieve the latest block
+        let latest = self
+            .client
+            .latest_header()?
+            .ok_or_else(|| PayloadBuilderError::MissingParentBlock(attributes.parent))?;
+
+        // only build on the latest block
+        if attributes.parent != latest.hash() {
+            return Err(PayloadBuilderError::Internal(RethError::Custom(
+                "must build on latest block".into(),
+            )));
+        }
+
+        let attributes = PayloadAttributes {
+            inner: attributes,
+            extra_data: self.extra_data,
+            wallet: self.wallet.clone(),
+        };
+
+        let parent = Arc::new(latest.header.seal_slow());
+        let config = JobConfig {
+            attributes,
+            chain: Arc::clone(&self.chain),
+            parent,
+            algorithms: self.algorithms.clone(),
+        };
+
+        let deadline = Box::pin(sleep(self.deadline));
+
+        let incoming = BroadcastStream::new(self.incoming.subscribe()).fuse();
+        let invalidated = BroadcastStream::new(self.invalidated.subscribe()).fuse();
+
+        Ok(Job::new(
+            config,
+            deadline,
+            Arc::clone(&self.client),
+            Arc::clone(&self.pool),
+            Arc::clone(&self.bundle_pool),
+            incoming,
+            invalidated,
+        ))
+    }
+}
+
+/// returns the EVM environment for the payload described by `config`
+fn evm_env(config: &JobConfig) -> (CfgEnv, BlockEnv) {
+    let (cfg_env, mut block_env) = config
+        .attributes
+        .inner
+        .cfg_and_block_env(&config.chain, &config.parent);
+
+    // mark the builder as the coinbase in the block env
+    block_env.coinbase = config.attributes.wallet.address().into();
+
+    (cfg_env, block_env)
+}
+
+/// returns the excess blob gas for the payload described by `config`, or `None` if cancun is not
+/// active for the payload
+fn excess_blob_gas(config: &JobConfig) -> Option<u64> {
+    let timestamp = config.attributes.inner.timestamp;
+    if !config
+        .chain
+        .fork(Hardfork::Cancun)
+        .active_at_timestamp(timestamp)
+    {
+        return None;
+    }
+
+    // NOTE: the parent of the first cancun block carries no blob gas fields
+    let parent = &config.parent;
+    Some(blob::calc_excess_blob_gas(
+        parent.excess_blob_gas.unwrap_or_default(),
+        parent.blob_gas_used.unwrap_or_default(),
+    ))
+}
+
+impl SimulatedBundle {
+    fn new(id: BundleId, bundle: BundleCompact, simulation: &Execution) -> Self {
+        // NOTE: simulations start from zero cumulative gas
+        let score =
+            effective_gas_price(simulation.coinbase_payment, simulation.cumulative_gas_used);
+        Self {
+            id,
+            bundle,
+            score,
+            access: simulation.access.clone(),
+        }
+    }
+}
+
+/// the coinbase payment per unit of gas used
+fn effective_gas_price(coinbase_payment: U256, gas_used: u64) -> U256 {
+    coinbase_payment / U256::from(gas_used.max(1))
+}
+
+/// the results of simulating bundles in isolation. a bundle maps to `None` if its execution failed
+/// or if any of its transactions reverted without being allowed to revert.
+type Simulations = Vec<(BundleId, Option<Execution>)>;
+
+/// simulates each of `bundles` in isolation on top of the parent state
+fn simulate<Client>(
+    config: JobConfig,
+    client: Arc<Client>,
+    bundles: Vec<(BundleId, BundleCompact)>,
+) -> Result<Simulations, PayloadBuilderError>
+where
+    Client: StateProviderFactory,
+{
+    let state = client.state_by_block_hash(config.parent.hash)?;
+    let state = Arc::new(State::new(state));
+
+    let (cfg_env, block_env) = evm_env(&config);
+    let blob_gas_price = blob::blob_gas_price(excess_blob_gas(&config).unwrap_or_default());
+
+    let simulations = bundles
+        .into_iter()
+        .map(|(id, bundle)| {
+            let mut db = CacheDB::new(Arc::clone(&state));
+            let mut post_state = PostState::default();
+            let execution = execute(
+                &mut db,
+                &mut post_state,
+                &cfg_env,
+                &block_env,
+                blob_gas_price,
+                0,
+                bundle.txs.clone(),
+                None,
+            )
+            .ok()
+            .filter(|execution| bundle.allows_reverts(execution.reverted_txs.iter()));
+            (id, execution)
+        })
+        .collect();
+
+    Ok(simulations)
+}
+
+fn build<Client, P, I>(
+    config: JobConfig,
+    client: Arc<Client>,
+    pool: P,
+    bundles: I,
+    algorithm: &dyn Algorithm,
+) -> Result<Payload, PayloadBuilderError>
+where
+    Client: StateProviderFactory,
+    P: TransactionPool,
+    I: IntoIterator<Item = SimulatedBundle>,
+{
+    let state = client.state_by_block_hash(config.parent.hash)?;
+    let state = State::new(state);
+    let unpackaged_payload = build_on_state(config, state, pool, bundles, algorithm)?;
+    unpackaged_payload.package()
+}
+
+fn build_on_state<S, P, I>(
+    config: JobConfig,
+    state: State<S>,
+    pool: P,
+    bundles: I,
+    algorithm: &dyn Algorithm,
+) -> Result<UnpackagedPayload<S>, PayloadBuilderError>
+where
+    S: StateProvider,
+    P: TransactionPool,
+    I: IntoIterator<Item = SimulatedBundle>,
+{
+    let state = Arc::new(state);
+
+    let (cfg_env, block_env) = evm_env(&config);
+
+    let block_num = block_env.number.to::<u64>();
+    let base_fee = block_env.basefee.to::<u64>();
+    let block_gas_limit: u64 = block_env.gas_limit.try_into().unwrap_or(u64::MAX);
+
+    // reserve gas for end-of-block proposer payment
+    const PROPOSER_PAYMENT_GAS_ALLOWANCE: u64 = 21000;
+    let execution_gas_limit = block_gas_limit - PROPOSER_PAYMENT_GAS_ALLOWANCE;
+
+    // prior to cancun, there is no blob gas available
+    let excess_blob_gas = excess_blob_gas(&config);
+    let blob_gas_limit = excess_blob_gas.map_or(0, |_| blob::MAX_BLOB_GAS_PER_BLOCK);
+    let blob_gas_price = blob::blob_gas_price(excess_blob_gas.unwrap_or_default());
+
+    // the bundles carry the sidecars of their blob transactions
+    let bundles: Vec<SimulatedBundle> = bundles.into_iter().collect();
+    let mut sidecars: HashMap<TxHash, Arc<BlobTransactionSidecar>> = bundles
+        .iter()
+        .flat_map(|bundle| bundle.bundle.sidecars.0.clone())
+        .collect();
+
+    let mut db = CacheDB::new(Arc::clone(&state));
+    let mut post_state = PostState::default();
+
+    // store the root of the parent beacon block in the beacon roots contract before the
+    // execution of any transaction
+    let parent_beacon_block_root =
+        excess_blob_gas.and(config.attributes.inner.parent_beacon_block_root);
+    if let Some(root) = parent_beacon_block_root {
+        apply_beacon_root_contract_call(&mut db, &mut post_state, &cfg_env, &block_env, root)
+            .map_err(PayloadBuilderError::EvmExecutionError)?;
+    }
+
+    // fill the payload with bundles and mempool transactions
+    let mut block = BlockState {
+        db,
+        post_state,
+        cfg_env,
+        block_env,
+        gas_limit: execution_gas_limit,
+        cumulative_gas_used: 0,
+        blob_gas_limit,
+        blob_gas_used: 0,
+        blob_gas_price,
+        coinbase_payment: U256::ZERO,
+        txs: Vec::new(),
+        bundles: HashSet::new(),
+        accessed: AccessSet::default(),
+    };
+    let mut mempool = PoolTransactions {
+        inner: pool.best_transactions_with_base_fee(base_fee),
+        pool: &pool,
+        yielded: HashMap::new(),
+        sidecars: HashMap::new(),
+    };
+    algorithm.fill(&mut block, bundles, &mut mempool)?;
+    sidecars.extend(mempool.sidecars);
+
+    let BlockState {
+        mut db,
+        mut post_state,
+        cfg_env,
+        block_env,
+        mut cumulative_gas_used,
+        blob_gas_used,
+        coinbase_payment,
+        mut txs,
+        bundles: bundle_ids,
+        ..
+    } = block;
+
+    // collect the blobs of the blob transactions in the payload
+    let mut blobs_bundle = BlobsBundle::default();
+    for tx in txs.iter().filter(|tx| blob::blob_gas(tx) > 0) {
+        let sidecar = sidecars.get(&tx.hash()).ok_or_else(|| {
+            PayloadBuilderError::Internal(RethError::Custom(format!(
+                "missing sidecar for blob transaction {}",
+                tx.hash()
+            )))
+        })?;
+        blobs_bundle.extend(sidecar);
+    }
+
+    // construct payment to proposer fee recipient.
+    //
+    // NOTE: we give the entire coinbase payment to the proposer, except for the gas that we need
+    // to execute the transaction. if the coinbase payment cannot cover the gas cost to pay the
+    // proposer, then we do not make any payment.
+    let payment_tx_gas_cost = block_env.basefee * U256::from(PROPOSER_PAYMENT_GAS_ALLOWANCE);
+    let proposer_payment = coinbase_payment.saturating_sub(payment_tx_gas_cost);
+    if proposer_payment > U256::ZERO {
+        let builder_acct = db
+            .basic(block_env.coinbase)?
+            .expect("builder account exists if coinbase payment non-zero");
+        let payment_tx = proposer_payment_tx(
+            &config.attributes.wallet,
+            builder_acct.nonce,
+            base_fee,
+            cfg_env.chain_id.to::<u64>(),
+            &config.attributes.inner.suggested_fee_recipient,
+            proposer_payment,
+        );
+
+        // execute payment to proposer fee recipient
+        //
+        // if the payment transaction fails, then the entire payload build fails
+        let execution = execute(
+            &mut db,
+            &mut post_state,
+            &cfg_env,
+            &block_env,
+            blob_gas_price,
+            cumulative_gas_used,
+            Some(payment_tx.clone()),
+            None,
+        )
+        .map_err(PayloadBuilderError::EvmExecutionError)?;
+        cumulative_gas_used = execution.cumulative_gas_used;
+        for receipt in execution.receipts {
+            post_state.add_receipt(block_num, receipt);
+        }
+        txs.push(payment_tx);
+    }
+
+    // NOTE: here we assume post-shanghai
+    let balance_increments = post_block_withdrawals_balance_increments(
+        &config.chain,
+        config.attributes.inner.timestamp,
+        &config.attributes.inner.withdrawals,
+    );
+    for (address, increment) in balance_increments {
+        increment_account_balance(&mut db, &mut post_state, block_num, address, increment)?;
+    }
+
+    Ok(UnpackagedPayload {
+        attributes: config.attributes.inner,
+        block_env,
+        state,
+        post_state,
+        extra_data: config.attributes.extra_data,
+        txs: txs.into_iter().map(|tx| tx.into_signed()).collect(),
+        bundles: bundle_ids,
+        cumulative_gas_used,
+        blob_gas_used: excess_blob_gas.map(|_| blob_gas_used),
+        excess_blob_gas,
+        parent_beacon_block_root,
+        blobs_bundle,
+        proposer_payment,
+        algorithm: algorithm.name(),
+    })
+}
+
+/// the state of a payload under construction
+struct BlockState<S: StateProvider> {
+    db: CacheDB<Arc<State<S>>>,
+    post_state: PostState,
+    cfg_env: CfgEnv,
+    block_env: BlockEnv,
+    /// the gas available for the execution of bundles and transactions
+    gas_limit: u64,
+    cumulative_gas_used: u64,
+    /// the blob gas available for blob transactions
+    blob_gas_limit: u64,
+    blob_gas_used: u64,
+    blob_gas_price: U256,
+    coinbase_payment: U256,
+    txs: Vec<TransactionSignedEcRecovered>,
+    bundles: HashSet<BundleId>,
+    accessed: AccessSet,
+}
+
+impl<S: StateProvider> BlockState<S> {
+    fn commit(&mut self, execution: Execution) {
+        let block_num = self.block_env.number.to::<u64>();
+        for receipt in execution.receipts {
+            self.post_state.add_receipt(block_num, receipt);
+        }
+
+        self.accessed.extend(&execution.access);
+        self.coinbase_payment += execution.coinbase_payment;
+        self.cumulative_gas_used = execution.cumulative_gas_used;
+    }
+}
+
+impl<S: StateProvider> PendingBlock for BlockState<S> {
+    fn base_fee(&self) -> u64 {
+        self.block_env.basefee.to::<u64>()
+    }
+
+    fn gas_remaining(&self) -> u64 {
+        self.gas_limit - self.cumulative_gas_used
+    }
+
+    fn blob_gas_remaining(&self) -> u64 {
+        self.blob_gas_limit - self.blob_gas_used
+    }
+
+    fn blob_gas_price(&self) -> U256 {
+        self.blob_gas_price
+    }
+
+    fn accessed(&self) -> &AccessSet {
+        &self.accessed
+    }
+
+    fn merge_bundle(
+        &mut self,
+        id: BundleId,
+        bundle: &BundleCompact,
+        accept: &mut dyn FnMut(U256) -> bool,
+    ) -> bool {
+        // check gas for entire bundle
+        let bundle_gas_limit: u64 = bundle.txs.iter().map(|tx| tx.gas_limit()).sum();
+        if bundle_gas_limit > self.gas_remaining() {
+            return false;
+        }
+
+        // check blob gas for entire bundle, and that each blob transaction pays the blob gas price
+        let bundle_blob_gas: u64 = bundle.txs.iter().map(|tx| blob::blob_gas(tx)).sum();
+        if bundle_blob_gas > self.blob_gas_remaining()
+            || !bundle
+                .txs
+                .iter()
+                .all(|tx| blob::pays_blob_gas_price(tx, self.blob_gas_price))
+        {
+            return false;
+        }
+
+        // execute the bundle into a separate post state, and journal the changes to the database.
+        // if the bundle is not merged, then we revert the database to the state prior to the
+        // execution and discard the post state, as if the execution was never attempted.
+        let mut journal = Journal::default();
+        let mut post_state = PostState::default();
+
+        let execution = match execute(
+            &mut self.db,
+            &mut post_state,
+            &self.cfg_env,
+            &self.block_env,
+            self.blob_gas_price,
+            self.cumulative_gas_used,
+            bundle.txs.clone(),
+            Some(&mut journal),
+        ) {
+            Ok(execution) => execution,
+            Err(_) => {
+                journal.revert(&mut self.db);
+                return false;
+            }
+        };
+
+        // if any transaction reverted that the bundle does not allow to revert, or if the payment
+        // of the bundle is not accepted, then we discard the bundle along with the state changes
+        // from its execution
+        let gas_used = execution.cumulative_gas_used - self.cumulative_gas_used;
+        if !bundle.allows_reverts(execution.reverted_txs.iter())
+            || !accept(effective_gas_price(execution.coinbase_payment, gas_used))
+        {
+            journal.revert(&mut self.db);
+            return false;
+        }
+
+        self.post_state.extend(post_state);
+        self.commit(execution);
+        self.blob_gas_used += bundle_blob_gas;
+        self.txs.extend(bundle.txs.iter().cloned());
+        self.bundles.insert(id);
+
+        true
+    }
+
+    fn execute_tx(&mut self, tx: &TransactionSignedEcRecovered) -> Result<(), EVMError<RethError>> {
+        // NOTE: we do not need to journal the changes here as we do for bundle execution, since
+        // the execution of a single transaction either succeeds or leaves the state untouched
+        let execution = execute(
+            &mut self.db,
+            &mut self.post_state,
+            &self.cfg_env,
+            &self.block_env,
+            self.blob_gas_price,
+            self.cumulative_gas_used,
+            Some(tx.clone()),
+            None,
+        )?;
+
+        self.commit(execution);
+        self.blob_gas_used += blob::blob_gas(tx);
+        self.txs.push(tx.clone());
+
+        Ok(())
+    }
+}
+
+/// the best transactions from a transaction pool
+struct PoolTransactions<'a, P: TransactionPool> {
+    inner: Box<dyn BestTransactions<Item = Arc<ValidPoolTransaction<P::Transaction>>>>,
+    pool: &'a P,
+    /// the transactions yielded so far, so that they can be marked invalid
+    yielded: HashMap<TxHash, Arc<ValidPoolTransaction<P::Transaction>>>,
+    /// the sidecars of the blob transactions yielded so far
+    sidecars: HashMap<TxHash, Arc<BlobTransactionSidecar>>,
+}
+
+impl<'a, P: TransactionPool> Mempool for PoolTransactions<'a, P> {
+    fn next_tx(&mut self) -> Option<TransactionSignedEcRecovered> {
+        loop {
+            let tx = self.inner.next()?;
+            let recovered = tx.to_recovered_transaction();
+
+            // a blob transaction can only be included along with its sidecar
+            if blob::blob_gas(&recovered) > 0 {
+                match self.pool.get_blob(*tx.hash()) {
+                    Ok(Some(sidecar)) => {
+                        self.sidecars.insert(*tx.hash(), Arc::new(sidecar));
+                    }
+                    _ => {
+                        self.inner.mark_invalid(&tx);
+                        continue;
+                    }
+                }
+            }
+
+            self.yielded.insert(*tx.hash(), tx);
+            return Some(recovered);
+        }
+    }
+
+    fn mark_invalid(&mut self, tx: &TransactionSignedEcRecovered) {
+        if let Some(tx) = self.yielded.get(&tx.hash()) {
+            self.inner.mark_invalid(tx);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Execution {
+    cumulative_gas_used: u64,
+    coinbase_payment: U256,
+    reverted_txs: Vec<TxHash>,
+    access: AccessSet,
+    /// the receipts of the executed transactions. the receipts are not added to the post state.
+    receipts: Vec<Receipt>,
+}
+
+/// the state of the accounts in a [`CacheDB`] prior to their modification, so that the
+/// modifications can be reverted without copying the entire database
+///
+/// NOTE: the journal keeps the info of each account along with the storage slots that are
+/// written, rather than the entire account. only a self-destruct or a contract creation, which
+/// clear the storage of the account, make the journal keep the entire storage.
+#[derive(Debug, Default)]
+struct Journal {
+    /// the info of each account prior to its first modification, or `None` if the account was not
+    /// in the database
+    accounts: HashMap<B160, Option<(AccountInfo, AccountState)>>,
+    /// the value of each storage slot prior to its first modification, or `None` if the slot was
+    /// not in the database
+    slots: HashMap<(B160, U256), Option<U256>>,
+    /// the storage of each account prior to its clearance
+    storage: HashMap<B160, HashMap<U256, U256>>,
+}
+
+impl Journal {
+    /// records the info of the account at `address` in `db`, unless the journal already contains
+    /// an earlier state of the account
+    fn record_info<ExtDB: DatabaseRef>(&mut self, db: &CacheDB<ExtDB>, address: B160) {
+        self.accounts.entry(address).or_insert_with(|| {
+            db.accounts
+                .get(&address)
+                .map(|account| (account.info.clone(), account.account_state.clone()))
+        });
+    }
+
+    /// records the state in `db` of the account at `address` that `account` is about to modify
+    fn record<ExtDB: DatabaseRef>(
+        &mut self,
+        db: &CacheDB<ExtDB>,
+        address: B160,
+        account: &Account,
+    ) {
+        self.record_info(db, address);
+
+        let prior = db.accounts.get(&address);
+        if account.is_selfdestructed() || account.is_created() {
+            self.storage
+                .entry(address)
+                .or_insert_with(|| prior.map(|prior| prior.storage.clone()).unwrap_or_default());
+        }
+
+        // NOTE: the recorded storage of an account holds all of the slots prior to any of the
+        // modifications that follow
+        if self.storage.contains_key(&address) {
+            return;
+        }
+        for slot in account.storage.keys() {
+            self.slots
+                .entry((address, *slot))
+                .or_insert_with(|| prior.and_then(|prior| prior.storage.get(slot).copied()));
+        }
+    }
+
+    /// reverts all of the accounts in `db` to their recorded state
+    fn revert<ExtDB: DatabaseRef>(self, db: &mut CacheDB<ExtDB>) {
+        // the slots modified prior to a clearance of the storage are recorded as slots, so we
+        // restore them after the storage
+        for (address, storage) in self.storage {
+            if let Some(account) = db.accounts.get_mut(&address) {
+                account.storage = storage;
+            }
+        }
+        for ((address, slot), value) in self.slots {
+            if let Some(account) = db.accounts.get_mut(&address) {
+                match value {
+                    Some(value) => account.storage.insert(slot, value),
+                    None => account.storage.remove(&slot),
+                };
+            }
+        }
+
+        for (address, account) in self.accounts {
+            match account {
+                Some((info, account_state)) => {
+                    let account = db.accounts.entry(address).or_default();
+                    account.info = info;
+                    account.account_state = account_state;
+                }
+                None => {
+                    db.accounts.remove(&address);
+                }
+            }
+        }
+    }
+}
+
+/// executes `txs` on top of `db`, committing the state changes to `db` and `post_state`. if
+/// `journal` is present, then the state of each account is recorded prior to its modification.
+///
+/// blob transactions are charged `blob_gas_price` per unit of blob gas.
+#[allow(clippy::too_many_arguments)]
+fn execute<S, I>(
+    db: &mut CacheDB<Arc<State<S>>>,
+    post_state: &mut PostState,
+    cfg_env: &CfgEnv,
+    block_env: &BlockEnv,
+    blob_gas_price: U256,
+    mut cumulative_gas_used: u64,
+    txs: I,
+    mut journal: Option<&mut Journal>,
+) -> Result<Execution, EVMError<RethError>>
+where
+    S: StateProvider,
+    I: IntoIterator<Item = TransactionSignedEcRecovered>,
+{
+    let block_num = block_env.number.to::<u64>();
+
+    // determine the initial balance of the account at the coinbase address
+    let coinbase_acct = db.basic(block_env.coinbase).map_err(EVMError::Database)?;
+    let initial_coinbase_balance = coinbase_acct.map_or(U256::ZERO, |acct| acct.balance);
+
+    let mut reverted_txs = Vec::new();
+    let mut access = AccessSet::default();
+    let mut receipts = Vec::new();
+    for tx in txs {
+        // burn the blob fee of the transaction before execution
+        //
+        // NOTE: the EVM does not account for blob gas, so we check that the sender can afford the
+        // maximum cost of the transaction, blob gas included, and deduct the blob fee from the
+        // balance of the sender ourselves. if the execution fails, then we undo the deduction.
+        let sender = tx.signer();
+        let blob_fee = U256::from(blob::blob_gas(&tx)) * blob_gas_price;
+        let mut charged = Journal::default();
+        let mut blob_fee_payment = None;
+        if blob_fee > U256::ZERO {
+            if !blob::pays_blob_gas_price(&tx, blob_gas_price) {
+                return Err(EVMError::Transaction(
+                    InvalidTransaction::GasPriceLessThanBasefee,
+                ));
+            }
+
+            let prior = db
+                .basic(sender)
+                .map_err(EVMError::Database)?
+                .unwrap_or_default();
+            let max_cost = U256::from(tx.gas_limit()) * U256::from(tx.max_fee_per_gas())
+                + U256::from(tx.value())
+                + blob::max_blob_fee(&tx);
+            if prior.balance < max_cost {
+                return Err(EVMError::Transaction(
+                    InvalidTransaction::LackOfFundForGasLimit {
+                        gas_limit: max_cost,
+                        balance: prior.balance,
+                    },
+                ));
+            }
+
+            if let Some(journal) = journal.as_deref_mut() {
+                journal.record_info(db, sender);
+            }
+            charged.record_info(db, sender);
+            let account = db.load_account(sender).map_err(EVMError::Database)?;
+            account.info.balance -= blob_fee;
+            blob_fee_payment = Some((prior, account.info.clone()));
+        }
+
+        // construct EVM
+        let tx_env = tx_env_with_recovered(&tx);
+        let env = Env {
+            cfg: cfg_env.clone(),
+            block: block_env.clone(),
+            tx: tx_env.clone(),
+        };
+        let mut evm = EVM::with_env(env);
+        evm.database(&mut *db);
+
+        // execute transaction
+        let ResultAndState { result, state } = match evm.transact() {
+            Ok(execution) => execution,
+            Err(err) => {
+                charged.revert(db);
+                return Err(err);
+            }
+        };
+
+        // record the deduction in the post state. the post state keeps the first change to an
+        // account, so the change set holds the balance of the sender prior to the blob fee.
+        if let Some((prior, charged)) = blob_fee_payment {
+            post_state.change_account(
+                block_num,
+                sender,
+                into_reth_acc(prior),
+                into_reth_acc(charged),
+            );
+        }
+
+        // record the state accessed by the transaction. we ignore the coinbase, since every
+        // transaction that pays a priority fee modifies it.
+        for (address, account) in state.iter() {
+            if *address == block_env.coinbase {
+                continue;
+            }
+
+            let prior = db
+                .basic(*address)
+                .map_err(EVMError::Database)?
+                .unwrap_or_default();
+            let written = prior.balance != account.info.balance
+                || prior.nonce != account.info.nonce
+                || prior.code_hash != account.info.code_hash;
+            access.record_account(*address, written);
+
+            for (slot, value) in account.storage.iter() {
+                let written = value.original_value != value.present_value;
+                access.record_slot(*address, *slot, written);
+            }
+        }
+
+        // commit changes to DB and post state
+        if let Some(journal) = journal.as_deref_mut() {
+            for (address, account) in state.iter() {
+                journal.record(db, *address, account);
+            }
+        }
+        commit_state_changes(db, post_state, block_num, state, true);
+
+        cumulative_gas_used += result.gas_used();
+
+        if !result.is_success() {
+            reverted_txs.push(tx.hash());
+        }
+
+        receipts.push(Receipt {
+            tx_type: tx.tx_type(),
+            success: result.is_success(),
+            cumulative_gas_used,
+            logs: result.logs().into_iter().map(into_reth_log).collect(),
+        });
+    }
+
+    // compute the coinbase payment
+    let coinbase_payment =
+        compute_coinbase_payment(&block_env.coinbase, initial_coinbase_balance, post_state);
+
+    Ok(Execution {
+        cumulative_gas_used,
+        coinbase_payment,
+        reverted_txs,
+        access,
+        receipts,
+    })
+}
+
+/// the address of the EIP-4788 beacon roots contract
+const BEACON_ROOTS_ADDRESS: B160 = B160([
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+]);
+
+/// the caller of the EIP-4788 system call
+const SYSTEM_ADDRESS: B160 = {
+    let mut address = [0xff; 20];
+    address[19] = 0xfe;
+    B160(address)
+};
+
+/// the gas available to the EIP-4788 system call, which does not count against the block
+const SYSTEM_CALL_GAS_LIMIT: u64 = 30_000_000;
+
+/// stores `parent_beacon_block_root` in the beacon roots contract with the EIP-4788 system call,
+/// committing the state changes to `db` and `post_state`
+///
+/// NOTE: the system call neither pays for gas nor produces a receipt. if the contract is not
+/// deployed, then the call is a no-op.
+fn apply_beacon_root_contract_call<S: StateProvider>(
+    db: &mut CacheDB<Arc<State<S>>>,
+    post_state: &mut PostState,
+    cfg_env: &CfgEnv,
+    block_env: &BlockEnv,
+    parent_beacon_block_root: H256,
+) -> Result<(), EVMError<RethError>> {
+    let contract = db.basic(BEACON_ROOTS_ADDRESS).map_err(EVMError::Database)?;
+    if contract.map_or(true, |contract| contract.code_hash == KECCAK_EMPTY) {
+        return Ok(());
+    }
+
+    let env = Env {
+        cfg: cfg_env.clone(),
+        block: BlockEnv {
+            basefee: U256::ZERO,
+            gas_limit: U256::from(SYSTEM_CALL_GAS_LIMIT),
+            ..block_env.clone()
+        },
+        tx: TxEnv {
+            caller: SYSTEM_ADDRESS,
+            transact_to: TransactTo::Call(BEACON_ROOTS_ADDRESS),
+            data: parent_beacon_block_root.as_bytes().to_vec().into(),
+            gas_limit: SYSTEM_CALL_GAS_LIMIT,
+            gas_price: U256::ZERO,
+            value: U256::ZERO,
+            nonce: None,
+            ..Default::default()
+        },
+    };
+    let mut evm = EVM::with_env(env);
+    evm.database(&mut *db);
+    let ResultAndState { mut state, .. } = evm.transact()?;
+
+    // the system call leaves the system account and the coinbase untouched
+    state.remove(&SYSTEM_ADDRESS);
+    state.remove(&block_env.coinbase);
+    commit_state_changes(db, post_state, block_env.number.to::<u64>(), state, true);
+
+    Ok(())
+}
+
+/// Computes the payment to `coinbase` based on `initial_balance` and `post_state`.
+///
+/// NOTE: If the ending balance is less than `initial_balance`, then we define the payment as zero.
+/// If the account has been deleted, then we define the payment as zero. If the account was not
+/// modified, then the payment will be zero.
+fn compute_coinbase_payment(
+    coinbase: &B160,
+    initial_balance: U256,
+    post_state: &PostState,
+) -> U256 {
+    match post_state.account(coinbase) {
+        Some(Some(acct)) => acct.balance.saturating_sub(initial_balance),
+        Some(None) => U256::ZERO,
+        None => U256::ZERO,
+    }
+}
+
+/// Constructs a transfer transaction to pay `amount` to `proposer`.
+fn proposer_payment_tx(
+    wallet: &LocalWallet,
+    nonce: u64,
+    base_fee: u64,
+    chain_id: u64,
+    proposer: &B160,
+    amount: U256,
+) -> TransactionSignedEcRecovered {
+    let tx = Eip1559TransactionRequest::new()
+        .from(wallet.address())
+        .to(NameOrAddress::Address(ethers::types::H160::from_slice(
+            proposer.as_bytes(),
+        )))
+        .gas(21000)
+        .max_fee_per_gas(base_fee)
+        .max_priority_fee_per_gas(0)
+        .value(amount)
+        .data(EthersBytes::default())
+        .access_list(AccessList::default())
+        .nonce(nonce)
+        .chain_id(chain_id);
+    let tx = TypedTransaction::Eip1559(tx);
+    let signature = wallet.sign_transaction_sync(&tx).expect("can sign tx");
+    let tx_encoded = tx.rlp_signed(&signature);
+    let tx = TransactionSigned::decode_enveloped(Bytes::from(tx_encoded.as_ref()))
+        .expect("can decode tx");
+    tx.into_ecrecovered().expect("can recover tx signer")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bundle::BlobSidecars;
+
+    use std::ops::RangeInclusive;
+
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, NameOrAddress,
+            H160 as EthersAddress,
+        },
+    };
+    use futures_util::task::noop_waker_ref;
+    use reth_payload_builder::PayloadId;
+    use reth_primitives::{
+        sign_message, Address, Bytes, ChainSpecBuilder, ForkCondition, Transaction,
+        TransactionKind, TxEip4844, TxType,
+    };
+    use reth_provider::test_utils::{ExtendedAccount, MockEthProvider};
+    use reth_revm::revm::primitives::{specification::SpecId, B256};
+
+    const TRANSFER_GAS_LIMIT: u64 = 21000;
+
+    /// a job config for a post-shanghai block built on top of an (empty) genesis parent
+    fn job_config(wallet: LocalWallet) -> JobConfig {
+        let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+        let parent = parent_header(0, 0);
+        let attributes = attributes(&parent);
+
+        JobConfig {
+            attributes: PayloadAttributes {
+                inner: attributes,
+                extra_data: 0,
+                wallet,
+            },
+            parent: Arc::new(parent),
+            chain: Arc::new(chain),
+            algorithms: algorithm::default_algorithms(),
+        }
+    }
+
+    fn parent_header(number: u64, timestamp: u64) -> SealedHeader {
+        Header {
+            number,
+            timestamp,
+            gas_limit: 30000000,
+            base_fee_per_gas: Some(0),
+            ..Default::default()
+        }
+        .seal_slow()
+    }
+
+    /// payload attributes for the slot after `parent`
+    fn attributes(parent: &SealedHeader) -> PayloadBuilderAttributes {
+        PayloadBuilderAttributes {
+            id: PayloadId::new([0; 8]),
+            parent: parent.hash,
+            timestamp: parent.timestamp + 12,
+            suggested_fee_recipient: Address::random(),
+            prev_randao: H256::random(),
+            withdrawals: vec![],
+            parent_beacon_block_root: None,
+        }
+    }
+
+    fn bundle(id: BundleId, block_num: u64, eligibility: RangeInclusive<u64>) -> Bundle {
+        Bundle {
+            id,
+            txs: vec![],
+            block_range: block_num..=block_num,
+            eligibility,
+            reverting_tx_hashes: vec![],
+            sidecars: Default::default(),
+            replacement_uuid: None,
+        }
+    }
+
+    /// a bundle that is merged as if its simulation paid nothing and accessed no state
+    fn unsimulated(id: BundleId, bundle: BundleCompact) -> SimulatedBundle {
+        SimulatedBundle {
+            id,
+            bundle,
+            score: U256::ZERO,
+            access: AccessSet::default(),
+        }
+    }
+
+    fn env(coinbase: Address, basefee: U256) -> (CfgEnv, BlockEnv) {
+        let cfg_env = CfgEnv {
+            chain_id: U256::from(1),
+            spec_id: SpecId::CANCUN,
+            ..Default::default()
+        };
+        let block_env = BlockEnv {
+            number: U256::ZERO,
+            coinbase,
+            timestamp: U256::ZERO,
+            difficulty: U256::ZERO,
+            prevrandao: Some(B256::random()),
+            basefee,
+            gas_limit: U256::from(15000000),
+        };
+
+        (cfg_env, block_env)
+    }
+
+    fn tx(
+        from: &LocalWallet,
+        to: EthersAddress,
+        gas_limit: u64,
+        max_fee_per_gas: u64,
+        max_priority_fee_per_gas: u64,
+        value: u64,
+        nonce: u64,
+    ) -> TransactionSignedEcRecovered {
+        let tx = Eip1559TransactionRequest::new()
+            .from(from.address())
+            .to(NameOrAddress::Address(to))
+            .gas(gas_limit)
+            .max_fee_per_gas(max_fee_per_gas)
+            .max_priority_fee_per_gas(max_priority_fee_per_gas)
+            .value(value)
+            .data(ethers::types::Bytes::default())
+            .access_list(ethers::types::transaction::eip2930::AccessList::default())
+            .nonce(nonce)
+            .chain_id(from.chain_id());
+        let tx = TypedTransaction::Eip1559(tx);
+        let signature = from.sign_transaction_sync(&tx).expect("can sign tx");
+        let tx_encoded = tx.rlp_signed(&signature);
+        let tx = TransactionSigned::decode_enveloped(Bytes::from(tx_encoded.as_ref()))
+            .expect("can decode tx");
+        tx.into_ecrecovered().expect("can recover tx signer")
+    }
+
+    #[test]
+    fn execute_transfer() {
+        let state = MockEthProvider::default();
+
+        // add sender account to state
+        let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let initial_sender_balance = 10000000;
+        let sender_nonce = 0;
+        let sender_account = ExtendedAccount::new(sender_nonce, U256::from(initial_sender_balance));
+        state.add_account(sender_wallet.address().into(), sender_account);
+
+        let state = State::new(state);
+        let mut db = CacheDB::new(Arc::new(state));
+        let mut post_state = PostState::default();
+
+        // builder will be the coinbase (i.e. beneficiary)
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+
+        let (cfg_env, block_env) = env(builder_wallet.address().into(), U256::ZERO);
+
+        let receiver_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let transfer_amount = 100;
+        let max_priority_fee = 100;
+        let max_fee = block_env.basefee.to::<u64>() + max_priority_fee;
+
+        // construct the transfer transaction for execution
+        let transfer_tx = tx(
+            &sender_wallet,
+            receiver_wallet.address(),
+            TRANSFER_GAS_LIMIT,
+            max_fee,
+            max_priority_fee,
+            transfer_amount,
+            sender_nonce,
+        );
+
+        // execute the transfer transaction
+        let execution = execute(
+            &mut db,
+            &mut post_state,
+            &cfg_env,
+            &block_env,
+            U256::ZERO,
+            0,
+            Some(transfer_tx),
+            None,
+        )
+        .expect("execution doesn't fail");
+        let Execution {
+            cumulative_gas_used,
+            coinbase_payment,
+            access,
+            receipts,
+            ..
+        } = execution;
+
+        // expected gas usage is the transfer transaction's gas limit
+        let expected_cumulative_gas_used = TRANSFER_GAS_LIMIT;
+
+        // check execution contains transaction receipt
+        let receipt = receipts.first().expect("execution contains receipt");
+        assert!(receipt.success);
+        assert_eq!(receipt.tx_type, TxType::EIP1559);
+        assert_eq!(receipt.cumulative_gas_used, expected_cumulative_gas_used);
+
+        // check post-execution sender balance
+        let sender_account = post_state
+            .account(&Address::from(sender_wallet.address()))
+            .expect("sender account touched")
+            .expect("sender account not destroyed");
+        let expected_sender_balance =
+            initial_sender_balance - transfer_amount - (max_fee * receipt.cumulative_gas_used);
+        assert_eq!(sender_account.balance, U256::from(expected_sender_balance));
+
+        // check post-execution receiver balance
+        let receiver_account = post_state
+            .account(&Address::from(receiver_wallet.address()))
+            .expect("receiver account touched")
+            .expect("receiver account not destroyed");
+        assert_eq!(receiver_account.balance, U256::from(transfer_amount));
+
+        // check gas usage
+        assert_eq!(cumulative_gas_used, expected_cumulative_gas_used);
+
+        // check coinbase payment
+        let expected_coinbase_payment = cumulative_gas_used * max_priority_fee;
+        let builder_account = post_state
+            .account(&Address::from(builder_wallet.address()))
+            .expect("builder account touched")
+            .expect("builder account not destroyed");
+        assert_eq!(
+            builder_account.balance,
+            U256::from(expected_coinbase_payment)
+        );
+        assert_eq!(coinbase_payment, U256::from(expected_coinbase_payment));
+
+        // check accessed state. the coinbase is not recorded.
+        let sender = Address::from(sender_wallet.address());
+        let receiver = Address::from(receiver_wallet.address());
+        let builder = Address::from(builder_wallet.address());
+        assert!(access.accounts.contains(&sender));
+        assert!(access.accounts.contains(&receiver));
+        assert!(!access.accounts.contains(&builder));
+        assert_eq!(access.accounts_written, HashSet::from([sender, receiver]));
+        assert!(access.slots_written.is_empty());
+    }
+
+    #[test]
+    fn execute_coinbase_transfer() {
+        let state = MockEthProvider::default();
+
+        // populate coinbase transfer smart contract in the DB
+        //
+        // h/t lightclient: https://github.com/lightclient/sendall
+        let contract_addr = Address::random();
+        let bytecode = vec![0x5f, 0x5f, 0x5f, 0x5f, 0x47, 0x41, 0x5a, 0xf1, 0x00];
+        let contract_acct = ExtendedAccount::new(0, U256::ZERO).with_bytecode(bytecode.into());
+        state.add_account(contract_addr, contract_acct);
+
+        // add caller account to state
+        let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let initial_sender_balance = 10000000;
+        let sender_nonce = 0;
+        let sender_account = ExtendedAccount::new(sender_nonce, U256::from(initial_sender_balance));
+        state.add_account(sender_wallet.address().into(), sender_account);
+
+        let state = State::new(state);
+        let mut db = CacheDB::new(Arc::new(state));
+        let mut post_state = PostState::default();
+
+        // builder will be the coinbase (i.e. beneficiary)
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+
+        let (cfg_env, block_env) = env(builder_wallet.address().into(), U256::ZERO);
+
+        let tx_value = 100;
+        let tx_gas_limit = 84000;
+        let max_priority_fee = 100;
+        let max_fee = block_env.basefee.to::<u64>() + max_priority_fee;
+
+        // construct the contract call transaction for execution
+        let call_tx = tx(
+            &sender_wallet,
+            EthersAddress(*contract_addr),
+            tx_gas_limit,
+            max_fee,
+            max_priority_fee,
+            tx_value,
+            sender_nonce,
+        );
+
+        let execution = execute(
+            &mut db,
+            &mut post_state,
+            &cfg_env,
+            &block_env,
+            U256::ZERO,
+            0,
+            Some(call_tx),
+            None,
+        )
+        .expect("execution doesn't fail");
+        let Execution {
+            coinbase_payment,
+            cumulative_gas_used,
+            ..
+        } = execution;
+
+        // check coinbase payment
+        let expected_coinbase_payment = tx_value + (cumulative_gas_used * max_priority_fee);
+        let builder_account = post_state
+            .account(&Address::from(builder_wallet.address()))
+            .expect("builder account touched")
+            .expect("builder account not destroyed");
+        assert_eq!(
+            builder_account.balance,
+            U256::from(expected_coinbase_payment)
+        );
+        assert_eq!(coinbase_payment, U256::from(expected_coinbase_payment));
+    }
+
+    #[test]
+    fn build_discards_bundle_with_disallowed_revert() {
+        let state = MockEthProvider::default();
+
+        // populate contract that always reverts in the DB
+        let revert_addr = Address::random();
+        let bytecode = vec![0x5f, 0x5f, 0xfd];
+        let revert_acct = ExtendedAccount::new(0, U256::ZERO).with_bytecode(bytecode.into());
+        state.add_account(revert_addr, revert_acct);
+
+        // add caller account to state
+        let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let sender_nonce = 0;
+        let sender_account = ExtendedAccount::new(sender_nonce, U256::from(10000000));
+        state.add_account(sender_wallet.address().into(), sender_account);
+
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let config = job_config(builder_wallet);
+
+        let revert_tx = tx(
+            &sender_wallet,
+            EthersAddress(*revert_addr),
+            50000,
+            100,
+            100,
+            0,
+            sender_nonce,
+        );
+
+        // the bundle is discarded if the revert is not allowed
+        let bundle = BundleCompact {
+            txs: vec![revert_tx.clone()],
+            reverting_tx_hashes: vec![],
+            sidecars: Default::default(),
+        };
+        let payload = build_on_state(
+            config.clone(),
+            State::new(state.clone()),
+            NoopTransactionPool::default(),
+            Some(unsimulated(0, bundle)),
+            &BundlesFirst,
+        )
+        .expect("build doesn't fail");
+        assert!(payload.bundles.is_empty());
+        assert!(payload.txs.is_empty());
+        assert_eq!(payload.cumulative_gas_used, 0);
+
+        // the bundle is included if the revert is allowed
+        let bundle = BundleCompact {
+            txs: vec![revert_tx.clone()],
+            reverting_tx_hashes: vec![revert_tx.hash()],
+            sidecars: Default::default(),
+        };
+        let payload = build_on_state(
+            config,
+            State::new(state),
+            NoopTransactionPool::default(),
+            Some(unsimulated(0, bundle)),
+            &BundlesFirst,
+        )
+        .expect("build doesn't fail");
+        assert!(payload.bundles.contains(&0));
+        assert_eq!(payload.txs[0].hash(), revert_tx.hash());
+    }
+
+    /// a type-3 transfer carrying `num_blobs` blobs
+    fn blob_tx(from: &LocalWallet, num_blobs: usize, nonce: u64) -> TransactionSignedEcRecovered {
+        blob_tx_with_max_fee(from, num_blobs, nonce, 1)
+    }
+
+    fn blob_tx_with_max_fee(
+        from: &LocalWallet,
+        num_blobs: usize,
+        nonce: u64,
+        max_fee_per_blob_gas: u128,
+    ) -> TransactionSignedEcRecovered {
+        let tx = Transaction::Eip4844(TxEip4844 {
+            chain_id: from.chain_id(),
+            nonce,
+            gas_limit: TRANSFER_GAS_LIMIT,
+            max_fee_per_gas: 100,
+            max_priority_fee_per_gas: 100,
+            to: TransactionKind::Call(Address::random()),
+            blob_versioned_hashes: (0..num_blobs).map(|_| H256::random()).collect(),
+            max_fee_per_blob_gas,
+            ..Default::default()
+        });
+        let secret = H256::from_slice(&from.signer().to_bytes());
+        let signature = sign_message(secret, tx.signature_hash()).expect("can sign tx");
+        TransactionSigned::from_transaction_and_signature(tx, signature)
+            .into_ecrecovered()
+            .expect("can recover tx signer")
+    }
+
+    #[test]
+    fn execute_charges_blob_fee_upfront() {
+        let state = MockEthProvider::default();
+
+        // the sender can afford the blob gas price, but not the maximum blob fee of its transaction
+        let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let blob_gas_price = U256::from(1);
+        let gas_cost = TRANSFER_GAS_LIMIT * 100;
+        let initial_sender_balance = gas_cost + blob::BLOB_GAS_PER_BLOB;
+        let sender_account = ExtendedAccount::new(0, U256::from(initial_sender_balance));
+        let sender = sender_wallet.address().into();
+        state.add_account(sender, sender_account);
+
+        let mut db = CacheDB::new(Arc::new(State::new(state)));
+        let mut post_state = PostState::default();
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let (cfg_env, block_env) = env(builder_wallet.address().into(), U256::ZERO);
+        let mut execute_tx =
+            |db: &mut CacheDB<_>, tx: TransactionSignedEcRecovered, blob_gas_price: U256| {
+                execute(
+                    db,
+                    &mut post_state,
+                    &cfg_env,
+                    &block_env,
+                    blob_gas_price,
+                    0,
+                    Some(tx),
+                    None,
+                )
+            };
+
+        let tx = blob_tx_with_max_fee(&sender_wallet, 1, 0, 2);
+        assert!(execute_tx(&mut db, tx, blob_gas_price).is_err());
+
+        // a transaction that does not pay the blob gas price is invalid
+        let tx = blob_tx_with_max_fee(&sender_wallet, 1, 0, 1);
+        assert!(execute_tx(&mut db, tx.clone(), U256::from(2)).is_err());
+
+        // neither failure leaves a trace on the balance of the sender
+        let balance = db.basic(sender).unwrap().unwrap().balance;
+        assert_eq!(balance, U256::from(initial_sender_balance));
+
+        execute_tx(&mut db, tx, blob_gas_price).expect("execution doesn't fail");
+        let sender = post_state
+            .account(&sender)
+            .expect("sender account modified")
+            .expect("sender account exists");
+        assert_eq!(sender.balance, U256::ZERO);
+    }
+
+    #[test]
+    fn build_respects_blob_gas_limit() {
+        let state = MockEthProvider::default();
+
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let config = JobConfig {
+            chain: Arc::new(
+                ChainSpecBuilder::mainnet()
+                    .shanghai_activated()
+                    .with_fork(Hardfork::Cancun, ForkCondition::Timestamp(0))
+                    .build(),
+            ),
+            ..job_config(builder_wallet)
+        };
+
+        // one single-blob bundle more than fits into a block
+        let max_blobs = (blob::MAX_BLOB_GAS_PER_BLOCK / blob::BLOB_GAS_PER_BLOB) as BundleId;
+        let mut senders = Vec::new();
+        let mut bundles = Vec::new();
+        for id in 0..=max_blobs {
+            let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+            let sender_account = ExtendedAccount::new(0, U256::from(10000000));
+            state.add_account(sender_wallet.address().into(), sender_account);
+
+            let tx = blob_tx(&sender_wallet, 1, 0);
+            let sidecars = BlobSidecars(HashMap::from([(
+                tx.hash(),
+                Arc::new(BlobTransactionSidecar::default()),
+            )]));
+            let bundle = BundleCompact {
+                txs: vec![tx],
+                reverting_tx_hashes: vec![],
+                sidecars,
+            };
+            bundles.push(unsimulated(id, bundle));
+            senders.push(sender_wallet);
+        }
+
+        let payload = build_on_state(
+            config,
+            State::new(state),
+            NoopTransactionPool::default(),
+            bundles,
+            &BundlesFirst,
+        )
+        .expect("build doesn't fail");
+        assert_eq!(payload.bundles.len() as BundleId, max_blobs);
+        assert!(!payload.bundles.contains(&max_blobs));
+        assert_eq!(payload.blob_gas_used, Some(blob::MAX_BLOB_GAS_PER_BLOCK));
+        assert_eq!(payload.excess_blob_gas, Some(0));
+
+        // the sender pays for execution gas as well as blob gas
+        let sender = payload
+            .post_state
+            .account(&senders[0].address().into())
+            .expect("sender account modified")
+            .expect("sender account exists");
+        let blob_fee = blob::BLOB_GAS_PER_BLOB * blob::blob_gas_price(0).to::<u64>();
+        assert_eq!(
+            sender.balance,
+            U256::from(10000000 - TRANSFER_GAS_LIMIT * 100 - blob_fee)
+        );
+    }
+
+    #[test]
+    fn build_stores_parent_beacon_block_root() {
+        // the runtime code of the beacon roots contract, as per EIP-4788
+        const BEACON_ROOTS_CODE: &str = "3373fffffffffffffffffffffffffffffffffffffffe14604d57602036146024575f5ffd5b5f35801560495762001fff810690815414603c575f5ffd5b62001fff01545f5260205ff35b5f5ffd5b62001fff42064281555f359062001fff015500";
+        const HISTORY_BUFFER_LENGTH: u64 = 8191;
+
+        let state = MockEthProvider::default();
+        let contract = ExtendedAccount::new(1, U256::ZERO)
+            .with_bytecode(Bytes::from(hex::decode(BEACON_ROOTS_CODE).unwrap()));
+        state.add_account(BEACON_ROOTS_ADDRESS, contract);
+
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let mut config = JobConfig {
+            chain: Arc::new(
+                ChainSpecBuilder::mainnet()
+                    .shanghai_activated()
+                    .with_fork(Hardfork::Cancun, ForkCondition::Timestamp(0))
+                    .build(),
+            ),
+            ..job_config(builder_wallet)
+        };
+        let root = H256::random();
+        config.attributes.inner.parent_beacon_block_root = Some(root);
+        let timestamp = config.attributes.inner.timestamp;
+
+        let payload = build_on_state(
+            config,
+            State::new(state),
+            NoopTransactionPool::default(),
+            vec![],
+            &BundlesFirst,
+        )
+        .expect("build doesn't fail");
+        assert_eq!(payload.parent_beacon_block_root, Some(root));
+
+        // the contract stores the timestamp and the root in its ring buffers
+        let storage = &payload
+            .post_state
+            .storage()
+            .get(&BEACON_ROOTS_ADDRESS)
+            .expect("contract storage modified")
+            .storage;
+        let timestamp_index = timestamp % HISTORY_BUFFER_LENGTH;
+        let root_index = timestamp_index + HISTORY_BUFFER_LENGTH;
+        assert_eq!(
+            storage.get(&U256::from(timestamp_index)),
+            Some(&U256::from(timestamp))
+        );
+        assert_eq!(
+            storage.get(&U256::from(root_index)),
+            Some(&U256::from_be_bytes(root.to_fixed_bytes()))
+        );
+
+        // the system call pays no gas and leaves no trace of the system account
+        assert_eq!(payload.cumulative_gas_used, 0);
+        assert!(payload.post_state.account(&SYSTEM_ADDRESS).is_none());
+    }
+
+    #[tokio::test]
+    async fn bundle_eligibility_uses_payload_timestamp() {
+        let client = MockEthProvider::default();
+        let parent = parent_header(1, 1000);
+        let parent_block = Block {
+            header: parent.header.clone(),
+            ..Default::default()
+        };
+        client.add_header(parent.hash, parent.header.clone());
+        client.add_block(parent.hash, parent_block);
+
+        let config = BuilderConfig {
+            deadline: Duration::from_secs(12),
+            extra_data: 0,
+            wallet: LocalWallet::new(&mut rand::thread_rng()),
+            algorithms: algorithm::default_algorithms(),
+        };
+        let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+        let builder = Builder::new(config, chain, client, NoopTransactionPool::default());
+
+        let attributes = attributes(&parent);
+        let timestamp = attributes.timestamp;
+        let block_num = parent.number + 1;
+
+        // populate the pool with bundles eligible before, at, and after the payload timestamp
+        {
+            let mut pool = builder.bundle_pool.lock().unwrap();
+            pool.insert(bundle(0, block_num, 0..=u64::MAX));
+            pool.insert(bundle(1, block_num, timestamp + 1..=u64::MAX));
+            pool.insert(bundle(2, block_num, 0..=timestamp - 1));
+            pool.insert(bundle(3, block_num, timestamp..=timestamp));
+        }
+
+        let mut job = builder.new_payload_job(attributes).expect("can create job");
+        let mut ids = job.bundles.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![0, 3]);
+
+        // incoming bundles are filtered against the payload timestamp as well
+        let _ = builder
+            .incoming
+            .send(bundle(4, block_num, timestamp + 1..=u64::MAX));
+        let _ = builder
+            .incoming
+            .send(bundle(5, block_num, timestamp..=timestamp + 1));
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let _ = job.poll_unpin(&mut cx);
+
+        let mut ids = job.bundles.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![0, 3, 5]);
+    }
+
+    #[tokio::test]
+    async fn expired_replacement_withdraws_replaced_bundle() {
+        let config = BuilderConfig {
+            deadline: Duration::from_secs(12),
+            extra_data: 0,
+            wallet: LocalWallet::new(&mut rand::thread_rng()),
+            algorithms: algorithm::default_algorithms(),
+        };
+        let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+        let builder = Builder::new(
+            config,
+            chain,
+            MockEthProvider::default(),
+            NoopTransactionPool::default(),
+        );
+        let mut invalidated = builder.invalidated.subscribe();
+
+        let (bundle_flow, bundles) = mpsc::unbounded_channel();
+        let (_state_events, events) = mpsc::unbounded_channel();
+        builder.start(bundles, events);
+
+        let uuid = Uuid::from_u128(1);
+        let mut original = bundle(0, 1, 0..=u64::MAX);
+        original.replacement_uuid = Some(uuid);
+        let mut expired = bundle(1, 1, 0..=1);
+        expired.replacement_uuid = Some(uuid);
+        bundle_flow.send(BundleRequest::Send(original)).unwrap();
+        bundle_flow.send(BundleRequest::Send(expired)).unwrap();
+
+        // the expired replacement is ignored, but the original bundle is withdrawn all the same
+        assert_eq!(invalidated.recv().await.unwrap(), 0);
+        assert!(builder
+            .bundle_pool
+            .lock()
+            .unwrap()
+            .eligible(1, 1)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn lagging_job_resyncs_with_bundle_pool() {
+        let client = MockEthProvider::default();
+        let parent = parent_header(1, 1000);
+        let parent_block = Block {
+            header: parent.header.clone(),
+            ..Default::default()
+        };
+        client.add_header(parent.hash, parent.header.clone());
+        client.add_block(parent.hash, parent_block);
+
+        let config = BuilderConfig {
+            deadline: Duration::from_secs(12),
+            extra_data: 0,
+            wallet: LocalWallet::new(&mut rand::thread_rng()),
+            algorithms: algorithm::default_algorithms(),
+        };
+        let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+        let builder = Builder::new(config, chain, client, NoopTransactionPool::default());
+        let block_num = parent.number + 1;
+
+        let uuid = Uuid::from_u128(1);
+        let mut original = bundle(0, block_num, 0..=u64::MAX);
+        original.replacement_uuid = Some(uuid);
+        builder.bundle_pool.lock().unwrap().insert(original);
+
+        let mut job = builder
+            .new_payload_job(attributes(&parent))
+            .expect("can create job");
+        assert_eq!(job.replacements.get(&uuid), Some(&0));
+
+        // the replacement is lost among more broadcasts than the job can buffer
+        let mut replacement = bundle(1, block_num, 0..=u64::MAX);
+        replacement.replacement_uuid = Some(uuid);
+        builder
+            .bundle_pool
+            .lock()
+            .unwrap()
+            .insert(replacement.clone());
+        let _ = builder.incoming.send(replacement);
+        for id in 2..300 {
+            let _ = builder
+                .incoming
+                .send(bundle(id, block_num + 1, 0..=u64::MAX));
+        }
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let _ = job.poll_unpin(&mut cx);
+
+        // the job picks up the replacement from the pool, and drops the replaced bundle
+        assert!(!job.bundles.contains_key(&0));
+        assert!(job.bundles.contains_key(&1));
+        assert_eq!(job.replacements.get(&uuid), Some(&1));
+
+        // the job forgets the UUID of an invalidated bundle
+        let _ = builder.invalidated.send(1);
+        let _ = job.poll_unpin(&mut cx);
+        assert!(job.bundles.is_empty());
+        assert!(job.replacements.is_empty());
+    }
+
+    #[tokio::test]
+    async fn far_future_expiration_is_left_to_pool() {
+        let config = BuilderConfig {
+            deadline: Duration::from_secs(12),
+            extra_data: 0,
+            wallet: LocalWallet::new(&mut rand::thread_rng()),
+            algorithms: algorithm::default_algorithms(),
+        };
+        let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+        let builder = Builder::new(
+            config,
+            chain,
+            MockEthProvider::default(),
+            NoopTransactionPool::default(),
+        );
+        let mut invalidated = builder.invalidated.subscribe();
+
+        let (bundle_flow, bundles) = mpsc::unbounded_channel();
+        let (_state_events, events) = mpsc::unbounded_channel();
+        builder.start(bundles, events);
+
+        // the bundle expires centuries from now, which the expiration queue cannot track
+        let uuid = Uuid::from_u128(1);
+        let mut far_future = bundle(0, 1, 0..=10_000_000_000);
+        far_future.replacement_uuid = Some(uuid);
+        bundle_flow.send(BundleRequest::Send(far_future)).unwrap();
+        bundle_flow.send(BundleRequest::Cancel(uuid)).unwrap();
+
+        // the maintenance task survives the bundle, and still serves the cancellation
+        assert_eq!(invalidated.recv().await.unwrap(), 0);
+    }
+
+    #[test]
+    fn build_merges_bundles_by_effective_gas_price() {
+        let state = MockEthProvider::default();
+
+        // populate coinbase transfer smart contract with a balance in the DB. the first bundle to
+        // call the contract receives the entire balance.
+        let contract_addr = Address::random();
+        let bytecode = vec![0x5f, 0x5f, 0x5f, 0x5f, 0x47, 0x41, 0x5a, 0xf1, 0x00];
+        let contract_acct =
+            ExtendedAccount::new(0, U256::from(1000000000)).with_bytecode(bytecode.into());
+        state.add_account(contract_addr, contract_acct);
+
+        let wallets: Vec<_> = (0..3)
+            .map(|_| LocalWallet::new(&mut rand::thread_rng()))
+            .collect();
+        for wallet in &wallets {
+            let account = ExtendedAccount::new(0, U256::from(10000000));
+            state.add_account(wallet.address().into(), account);
+        }
+
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let config = job_config(builder_wallet);
+
+        // two calls to the contract with different priority fees, and a plain transfer whose
+        // priority fee lies in between the priority fees of the calls
+        let high = tx(
+            &wallets[0],
+            EthersAddress(*contract_addr),
+            84000,
+            10,
+            10,
+            0,
+            0,
+        );
+        let low = tx(
+            &wallets[1],
+            EthersAddress(*contract_addr),
+            84000,
+            1,
+            1,
+            0,
+            0,
+        );
+        let transfer = tx(
+            &wallets[2],
+            EthersAddress::random(),
+            TRANSFER_GAS_LIMIT,
+            5,
+            5,
+            0,
+            0,
+        );
+        let bundles = [high.clone(), low.clone(), transfer.clone()]
+            .into_iter()
+            .enumerate()
+            .map(|(id, tx)| {
+                let bundle = BundleCompact {
+                    txs: vec![tx],
+                    reverting_tx_hashes: vec![],
+                    sidecars: Default::default(),
+                };
+                (id as BundleId, bundle)
+            })
+            .collect::<Vec<_>>();
+
+        let simulations = simulate(config.clone(), Arc::new(state.clone()), bundles.clone())
+            .expect("simulation doesn't fail");
+        let bundles = bundles
+            .into_iter()
+            .zip(simulations)
+            .map(|((id, bundle), (_, simulation))| {
+                let simulation = simulation.expect("bundle is valid on parent state");
+                SimulatedBundle::new(id, bundle, &simulation)
+            })
+            .collect::<Vec<_>>();
+
+        // both calls claim the contract balance in simulation, so they outrank the transfer
+        assert!(bundles[0].score > bundles[1].score);
+        assert!(bundles[1].score > bundles[2].score);
+
+        // the low fee call conflicts with the high fee call. once re-simulated, the low fee call
+        // pays less per gas than the transfer, so the transfer is merged first.
+        let payload = build_on_state(
+            config,
+            State::new(state),
+            NoopTransactionPool::default(),
+            bundles,
+            &BundlesFirst,
+        )
+        .expect("build doesn't fail");
+        assert_eq!(payload.bundles, HashSet::from([0, 1, 2]));
+        let hashes: Vec<_> = payload.txs.iter().take(3).map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![high.hash(), transfer.hash(), low.hash()]);
+    }
+
+    #[tokio::test]
+    async fn job_serves_most_valuable_payload() {
+        let client = MockEthProvider::default();
+        let parent = parent_header(1, 1000);
+        let parent_block = Block {
+            header: parent.header.clone(),
+            ..Default::default()
+        };
+        client.add_header(parent.hash, parent.header.clone());
+        client.add_block(parent.hash, parent_block);
+
+        // add sender accounts to state
+        let wallets: Vec<_> = (0..2)
+            .map(|_| LocalWallet::new(&mut rand::thread_rng()))
+            .collect();
+        for wallet in &wallets {
+            let account = ExtendedAccount::new(0, U256::from(10000000));
+            client.add_account(wallet.address().into(), account);
+        }
+
+        let config = BuilderConfig {
+            deadline: Duration::from_secs(12),
+            extra_data: 0,
+            wallet: LocalWallet::new(&mut rand::thread_rng()),
+            algorithms: algorithm::default_algorithms(),
+        };
+        let chain = ChainSpecBuilder::mainnet().shanghai_activated().build();
+        let builder = Builder::new(config, chain, client, NoopTransactionPool::default());
+
+        let mut job = builder
+            .new_payload_job(attributes(&parent))
+            .expect("can create job");
+
+        // two transfers with different priority fees
+        let transfers: Vec<_> = [(0, 10), (1, 100)]
+            .into_iter()
+            .map(|(id, priority_fee)| {
+                let transfer = tx(
+                    &wallets[id],
+                    EthersAddress::random(),
+                    TRANSFER_GAS_LIMIT,
+                    priority_fee,
+                    priority_fee,
+                    0,
+                    0,
+                );
+                let bundle = BundleCompact {
+                    txs: vec![transfer],
+                    reverting_tx_hashes: vec![],
+                    sidecars: Default::default(),
+                };
+                unsimulated(id as BundleId, bundle)
+            })
+            .collect();
+
+        // build payloads of differing value in an order other than ascending or descending value
+        for bundles in [
+            vec![transfers[1].clone()],
+            vec![],
+            transfers.clone(),
+            vec![transfers[0].clone()],
+        ] {
+            let payload = build(
+                job.config.clone(),
+                Arc::clone(&job.client),
+                NoopTransactionPool::default(),
+                bundles,
+                &BundlesFirst,
+            )
+            .expect("build doesn't fail");
+            job.cache(payload);
+        }
+
+        // the job keeps the most valuable payload of the algorithm alone
+        assert_eq!(job.built_payloads.len(), 1);
+
+        let expected_payment = U256::from(TRANSFER_GAS_LIMIT * (10 + 100));
+        let best = job.best_payload().expect("best payload exists");
+        assert_eq!(best.fees(), expected_payment);
+
+        let (resolved, _) = job.resolve();
+        let resolved = resolved.await.expect("payload resolves");
+        assert_eq!(resolved.fees(), expected_payment);
+        assert_eq!(resolved.block().hash(), best.block().hash());
+    }
+
+    #[test]
+    fn journal_reverts_written_slots_only() {
+        let state = MockEthProvider::default();
+
+        let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+        state.add_account(
+            sender_wallet.address().into(),
+            ExtendedAccount::new(0, U256::from(10000000)),
+        );
+
+        // a contract that stores 1 in slot 0
+        let contract_addr = Address::random();
+        let bytecode = vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x00];
+        let contract_acct = ExtendedAccount::new(0, U256::ZERO)
+            .with_bytecode(bytecode.into())
+            .extend_storage([
+                (H256::from_low_u64_be(0), U256::from(5)),
+                (H256::from_low_u64_be(1), U256::from(7)),
+            ]);
+        state.add_account(contract_addr, contract_acct);
+
+        let mut db = CacheDB::new(Arc::new(State::new(state)));
+        db.insert_account_storage(contract_addr, U256::from(1), U256::from(7))
+            .unwrap();
+        let mut post_state = PostState::default();
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let (cfg_env, block_env) = env(builder_wallet.address().into(), U256::ZERO);
+
+        let call = tx(
+            &sender_wallet,
+            EthersAddress(*contract_addr),
+            84000,
+            10,
+            10,
+            0,
+            0,
+        );
+        let mut journal = Journal::default();
+        execute(
+            &mut db,
+            &mut post_state,
+            &cfg_env,
+            &block_env,
+            U256::ZERO,
+            0,
+            Some(call),
+            Some(&mut journal),
+        )
+        .expect("execution doesn't fail");
+        assert_eq!(
+            db.storage(contract_addr, U256::ZERO).unwrap(),
+            U256::from(1)
+        );
+
+        // the journal holds the written slot, rather than the entire storage of the contract
+        assert_eq!(
+            journal.slots.keys().collect::<Vec<_>>(),
+            vec![&(contract_addr, U256::ZERO)]
+        );
+        assert!(journal.storage.is_empty());
+
+        journal.revert(&mut db);
+        assert_eq!(
+            db.storage(contract_addr, U256::ZERO).unwrap(),
+            U256::from(5)
+        );
+        assert_eq!(
+            db.accounts[&contract_addr].storage.get(&U256::from(1)),
+            Some(&U256::from(7))
+        );
+    }
+
+    #[test]
+    fn journal_reverts_execution() {
+        let state = MockEthProvider::default();
+
+        // add sender account to state
+        let sender_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let sender = Address::from(sender_wallet.address());
+        let initial_sender_balance = U256::from(10000000);
+        state.add_account(sender, ExtendedAccount::new(0, initial_sender_balance));
+
+        let state = State::new(state);
+        let mut db = CacheDB::new(Arc::new(state));
+        let mut post_state = PostState::default();
+
+        let builder_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let (cfg_env, block_env) = env(builder_wallet.address().into(), U256::ZERO);
+
+        let receiver_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let receiver = Address::from(receiver_wallet.address());
+        let transfer_tx = tx(
+            &sender_wallet,
+            receiver_wallet.address(),
+            TRANSFER_GAS_LIMIT,
+            100,
+            100,
+            100,
+            0,
+        );
+
+        let mut journal = Journal::default();
+        execute(
+            &mut db,
+            &mut post_state,
+            &cfg_env,
+            &block_env,
+            U256::ZERO,
+            0,
+            Some(transfer_tx),
+            Some(&mut journal),
+        )
+        .expect("execution doesn't fail");
+        let sender_account = db.basic(sender).unwrap().expect("sender account exists");
+        assert_eq!(sender_account.nonce, 1);
+
+        // the database is reverted to the state prior to the execution
+        journal.revert(&mut db);
+        let sender_account = db.basic(sender).unwrap().expect("sender account exists");
+        assert_eq!(sender_account.nonce, 0);
+        assert_eq!(sender_account.balance, initial_sender_balance);
+        let receiver_account = db.basic(receiver).unwrap().unwrap_or_default();
+        assert_eq!(receiver_account.balance, U256::ZERO);
+        let builder_account = db
+            .basic(builder_wallet.address().into())
+            .unwrap()
+            .unwrap_or_default();
+        assert_eq!(builder_account.balance, U256::ZERO);
+    }
+}
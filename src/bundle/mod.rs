@@ -1,27 +1,105 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::ops::RangeInclusive;
+use std::sync::Arc;
 
-use reth_primitives::{BlockNumber, TransactionSignedEcRecovered};
+use reth_primitives::{
+    Address, BlobTransactionSidecar, BlockNumber, TransactionSignedEcRecovered, TxHash, U256,
+};
+use uuid::Uuid;
 
 pub mod pool;
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
-pub(crate) struct BundleCompact(pub Vec<TransactionSignedEcRecovered>);
+pub struct BundleCompact {
+    pub txs: Vec<TransactionSignedEcRecovered>,
+    pub reverting_tx_hashes: Vec<TxHash>,
+    pub sidecars: BlobSidecars,
+}
 
 impl BundleCompact {
-    /// returns whether `self` conflicts with `other` in the sense that both cannot be executed
+    /// returns whether all of the `reverted` transactions are allowed to revert
+    pub fn allows_reverts<'a>(&self, mut reverted: impl Iterator<Item = &'a TxHash>) -> bool {
+        reverted.all(|hash| self.reverting_tx_hashes.contains(hash))
+    }
+}
+
+impl From<Bundle> for BundleCompact {
+    fn from(bundle: Bundle) -> Self {
+        Self {
+            txs: bundle.txs,
+            reverting_tx_hashes: bundle.reverting_tx_hashes,
+            sidecars: bundle.sidecars,
+        }
+    }
+}
+
+/// the sidecars of the blob transactions in a bundle, keyed by transaction hash
+///
+/// NOTE: the hash of a blob transaction commits to the versioned hashes of its blobs, so sidecars
+/// are compared and hashed by transaction hash only.
+#[derive(Clone, Debug, Default)]
+pub struct BlobSidecars(pub HashMap<TxHash, Arc<BlobTransactionSidecar>>);
+
+impl PartialEq for BlobSidecars {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.keys().all(|hash| other.0.contains_key(hash))
+    }
+}
+
+impl Eq for BlobSidecars {}
+
+impl Hash for BlobSidecars {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut hashes: Vec<_> = self.0.keys().collect();
+        hashes.sort();
+        hashes.hash(state);
+    }
+}
+
+/// the state accessed during the execution of a bundle
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AccessSet {
+    /// accounts whose balance, nonce, or code were accessed
+    pub accounts: HashSet<Address>,
+    /// accounts whose balance, nonce, or code were modified
+    pub accounts_written: HashSet<Address>,
+    /// storage slots that were accessed
+    pub slots: HashSet<(Address, U256)>,
+    /// storage slots that were modified
+    pub slots_written: HashSet<(Address, U256)>,
+}
+
+impl AccessSet {
+    pub fn record_account(&mut self, address: Address, written: bool) {
+        self.accounts.insert(address);
+        if written {
+            self.accounts_written.insert(address);
+        }
+    }
+
+    pub fn record_slot(&mut self, address: Address, slot: U256, written: bool) {
+        self.slots.insert((address, slot));
+        if written {
+            self.slots_written.insert((address, slot));
+        }
+    }
+
+    /// returns whether `self` conflicts with `other` in the sense that either one modifies state
+    /// that the other accesses
     pub fn conflicts(&self, other: &Self) -> bool {
-        let hashes = self
-            .0
-            .iter()
-            .map(|tx| tx.hash_ref())
-            .collect::<HashSet<_>>();
-        let other_hashes = other
-            .0
-            .iter()
-            .map(|tx| tx.hash_ref())
-            .collect::<HashSet<_>>();
-        !hashes.is_disjoint(&other_hashes)
+        !self.accounts_written.is_disjoint(&other.accounts)
+            || !other.accounts_written.is_disjoint(&self.accounts)
+            || !self.slots_written.is_disjoint(&other.slots)
+            || !other.slots_written.is_disjoint(&self.slots)
+    }
+
+    /// adds all of the state accessed in `other` to `self`
+    pub fn extend(&mut self, other: &Self) {
+        self.accounts.extend(&other.accounts);
+        self.accounts_written.extend(&other.accounts_written);
+        self.slots.extend(&other.slots);
+        self.slots_written.extend(&other.slots_written);
     }
 }
 
@@ -31,6 +109,67 @@ pub type BundleId = u64;
 pub struct Bundle {
     pub id: BundleId,
     pub txs: Vec<TransactionSignedEcRecovered>,
-    pub block_num: BlockNumber,
+    /// the (inclusive) range of block numbers that the bundle targets
+    pub block_range: RangeInclusive<BlockNumber>,
     pub eligibility: RangeInclusive<u64>,
+    /// hashes of the bundle transactions that are allowed to revert
+    pub reverting_tx_hashes: Vec<TxHash>,
+    /// searcher-supplied UUID. a newer bundle with the same UUID replaces the older one.
+    pub replacement_uuid: Option<Uuid>,
+    /// the sidecars of the blob transactions of the bundle
+    pub sidecars: BlobSidecars,
+}
+
+/// a request from a searcher. sends and cancellations travel together, so that they are applied
+/// in the order that they were received.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BundleRequest {
+    /// adds the bundle, replacing any earlier bundle with the same replacement UUID
+    Send(Bundle),
+    /// cancels the bundle with the replacement UUID
+    Cancel(Uuid),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_set_conflicts() {
+        let pool = Address::random();
+        let router = Address::random();
+
+        // two swaps through the same router against the same pool
+        let mut swap = AccessSet::default();
+        swap.record_account(router, false);
+        swap.record_account(pool, false);
+        swap.record_slot(pool, U256::from(8), true);
+        let mut other_swap = swap.clone();
+        other_swap.record_account(Address::random(), true);
+        assert!(swap.conflicts(&other_swap));
+        assert!(other_swap.conflicts(&swap));
+
+        // a read of the same pool conflicts with a write to the pool
+        let mut quote = AccessSet::default();
+        quote.record_account(pool, false);
+        quote.record_slot(pool, U256::from(8), false);
+        assert!(swap.conflicts(&quote));
+        assert!(quote.conflicts(&swap));
+
+        // calls through the same router do not conflict if they touch disjoint state
+        let mut transfer = AccessSet::default();
+        transfer.record_account(router, false);
+        transfer.record_slot(Address::random(), U256::from(1), true);
+        assert!(!swap.conflicts(&transfer));
+
+        // two reads never conflict
+        assert!(!quote.conflicts(&quote.clone()));
+
+        // a merged set conflicts with anything that conflicts with one of its parts
+        let mut merged = AccessSet::default();
+        merged.extend(&transfer);
+        assert!(!merged.conflicts(&swap));
+        merged.extend(&quote);
+        assert!(merged.conflicts(&swap));
+    }
 }
@@ -1,26 +1,55 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use super::{Bundle, BundleId};
 
-use reth_primitives::BlockNumber;
+use reth_primitives::{Address, BlockNumber};
 use reth_provider::CanonStateNotification;
+use uuid::Uuid;
 
 #[derive(Default)]
 pub struct BundlePool(pub(crate) HashSet<Bundle>);
 
 impl BundlePool {
-    /// returns all bundles eligible w.r.t. time `now` and canonical chain tip `block`
-    pub fn eligible(&self, block: BlockNumber, now: SystemTime) -> Vec<Bundle> {
-        let now = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    /// returns all bundles eligible for a payload with block number `block` and timestamp
+    /// `timestamp`
+    pub fn eligible(&self, block: BlockNumber, timestamp: u64) -> Vec<Bundle> {
         self.0
             .iter()
-            .filter(|bundle| bundle.eligibility.contains(&now) && bundle.block_num == block)
+            .filter(|bundle| {
+                bundle.eligibility.contains(&timestamp) && bundle.block_range.contains(&block)
+            })
             .cloned()
             .collect()
     }
 
+    /// inserts `bundle` into the pool. if `bundle` carries a replacement UUID, then any bundle
+    /// with the same UUID is removed from the pool.
+    ///
+    /// returns the ID of the replaced bundle, if any.
+    pub fn insert(&mut self, bundle: Bundle) -> Option<BundleId> {
+        let replaced = bundle.replacement_uuid.and_then(|uuid| self.cancel(uuid));
+        self.0.insert(bundle);
+        replaced
+    }
+
+    /// removes the bundle with replacement UUID `uuid` from the pool.
+    ///
+    /// returns the ID of the removed bundle, if any.
+    pub fn cancel(&mut self, uuid: Uuid) -> Option<BundleId> {
+        let bundle = self
+            .0
+            .iter()
+            .find(|bundle| bundle.replacement_uuid == Some(uuid))
+            .cloned()?;
+        self.0.remove(&bundle);
+        Some(bundle.id)
+    }
+
     /// removes all bundles whose eligibility expires w.r.t. time `now`
+    ///
+    /// NOTE: a payload is never built for a timestamp in the past, so a bundle whose eligibility
+    /// ends before `now` can never be included.
     pub fn tick(&mut self, now: SystemTime) {
         let now = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
         self.0.retain(|bundle| *bundle.eligibility.end() >= now);
@@ -28,9 +57,166 @@ impl BundlePool {
 
     /// maintains the pool based on updates to the canonical state.
     ///
+    /// removes all bundles whose block range ends at or below the new canonical tip, and all bundles
+    /// that contain a transaction whose nonce was consumed by the newly committed chain.
+    ///
+    /// returns the IDs of the bundles removed from the pool.
+    pub fn maintain(&mut self, event: CanonStateNotification) -> Vec<BundleId> {
+        let chain = match event {
+            CanonStateNotification::Commit { new } | CanonStateNotification::Reorg { new, .. } => {
+                new
+            }
+        };
+
+        // determine the highest nonce mined for each sender in the committed chain
+        let mut mined_nonces = HashMap::new();
+        for block in chain.blocks().values() {
+            for (tx, sender) in block.body.iter().zip(block.senders.iter()) {
+                let nonce = mined_nonces.entry(*sender).or_insert(tx.nonce());
+                *nonce = (*nonce).max(tx.nonce());
+            }
+        }
+
+        self.prune(chain.tip().number, &mined_nonces)
+    }
+
+    /// removes all bundles that are no longer valid on top of canonical chain tip `tip`, given the
+    /// highest nonce of each sender in `mined_nonces`.
+    ///
     /// returns the IDs of the bundles removed from the pool.
-    pub fn maintain(&mut self, _event: CanonStateNotification) -> Vec<BundleId> {
-        // remove all bundles
-        self.0.drain().map(|bundle| bundle.id).collect()
+    fn prune(&mut self, tip: BlockNumber, mined_nonces: &HashMap<Address, u64>) -> Vec<BundleId> {
+        let mut removed = Vec::new();
+        self.0.retain(|bundle| {
+            let expired = *bundle.block_range.end() <= tip;
+            let stale = bundle.txs.iter().any(|tx| {
+                mined_nonces
+                    .get(&tx.signer())
+                    .is_some_and(|mined| tx.nonce() <= *mined)
+            });
+
+            if expired || stale {
+                removed.push(bundle.id);
+                return false;
+            }
+            true
+        });
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::RAW_TX;
+    use reth_primitives::{hex, Bytes, TransactionSigned, TransactionSignedEcRecovered};
+
+    fn raw_tx() -> TransactionSignedEcRecovered {
+        let tx = Bytes::from(hex::decode(RAW_TX).unwrap());
+        let tx = TransactionSigned::decode_enveloped(tx).expect("can decode tx");
+        tx.into_ecrecovered().expect("can recover tx signer")
+    }
+
+    fn bundle(id: BundleId, replacement_uuid: Option<Uuid>) -> Bundle {
+        Bundle {
+            id,
+            txs: vec![],
+            block_range: 1..=1,
+            eligibility: 0..=u64::MAX,
+            reverting_tx_hashes: vec![],
+            sidecars: Default::default(),
+            replacement_uuid,
+        }
+    }
+
+    #[test]
+    fn insert_replaces_bundle_with_same_uuid() {
+        let mut pool = BundlePool::default();
+        let uuid = Uuid::from_u128(1);
+
+        assert_eq!(pool.insert(bundle(0, Some(uuid))), None);
+        assert_eq!(pool.insert(bundle(1, None)), None);
+        assert_eq!(pool.insert(bundle(2, Some(uuid))), Some(0));
+
+        let mut ids = pool.0.iter().map(|bundle| bundle.id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn cancel_removes_bundle() {
+        let mut pool = BundlePool::default();
+        let uuid = Uuid::from_u128(1);
+
+        pool.insert(bundle(0, Some(uuid)));
+        assert_eq!(pool.cancel(Uuid::from_u128(2)), None);
+        assert_eq!(pool.cancel(uuid), Some(0));
+        assert_eq!(pool.cancel(uuid), None);
+        assert!(pool.0.is_empty());
+    }
+
+    #[test]
+    fn prune_keeps_bundles_for_future_blocks() {
+        let mut pool = BundlePool::default();
+        for (id, block_range) in [(0, 1..=1), (1, 1..=2), (2, 3..=3), (3, 1..=5)] {
+            pool.insert(Bundle {
+                block_range,
+                ..bundle(id, None)
+            });
+        }
+
+        let mut removed = pool.prune(2, &HashMap::new());
+        removed.sort();
+        assert_eq!(removed, vec![0, 1]);
+        assert_eq!(pool.0.len(), 2);
+    }
+
+    #[test]
+    fn prune_removes_bundles_with_stale_nonces() {
+        let tx = raw_tx();
+        let mut pool = BundlePool::default();
+        pool.insert(Bundle {
+            txs: vec![tx.clone()],
+            block_range: 10..=10,
+            ..bundle(0, None)
+        });
+
+        // the nonce of the bundle transaction is still valid
+        let mined_nonces = HashMap::from([(tx.signer(), tx.nonce() - 1)]);
+        assert!(pool.prune(1, &mined_nonces).is_empty());
+
+        // the nonce of the bundle transaction was consumed
+        let mined_nonces = HashMap::from([(tx.signer(), tx.nonce())]);
+        assert_eq!(pool.prune(1, &mined_nonces), vec![0]);
+        assert!(pool.0.is_empty());
+    }
+
+    #[test]
+    fn eligible_honors_block_range() {
+        let mut pool = BundlePool::default();
+        pool.insert(Bundle {
+            block_range: 2..=4,
+            ..bundle(0, None)
+        });
+
+        assert!(pool.eligible(1, 0).is_empty());
+        for block in 2..=4 {
+            assert_eq!(pool.eligible(block, 0).len(), 1);
+        }
+        assert!(pool.eligible(5, 0).is_empty());
+    }
+
+    #[test]
+    fn eligible_honors_timestamp() {
+        let mut pool = BundlePool::default();
+        pool.insert(Bundle {
+            eligibility: 10..=20,
+            ..bundle(0, None)
+        });
+
+        assert!(pool.eligible(1, 9).is_empty());
+        assert_eq!(pool.eligible(1, 10).len(), 1);
+        assert_eq!(pool.eligible(1, 20).len(), 1);
+        assert!(pool.eligible(1, 21).is_empty());
     }
 }
@@ -1,42 +1,478 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::relay_endpoint::RelayEndpoint;
+use anyhow::{anyhow, Context, Result};
+use ethereum_consensus::primitives::BlsPublicKey;
+use serde::Deserialize;
 
-struct Config {
-    pub main_secret_key: &'static str,
+use crate::builder::algorithm::{default_algorithms, Algorithm};
+use crate::builder::BuilderConfig;
+use crate::keystore::KeystoreSource;
+use crate::relay_endpoint::{Encoding, RelayEndpoint};
+use crate::signing::{BidSigner, BuilderSigner, Network};
+use crate::web3signer::Web3Signer;
+
+/// the builder limits the extra data of its payloads to the bytes of a `u128`
+const MAX_EXTRA_DATA_BYTES: usize = 16;
+
+/// the configuration of the builder, as read from a TOML file. see `evangelion.example.toml`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "mainnet")]
+    pub network: Network,
+    pub builder: BuilderSettings,
+    #[serde(default)]
+    pub bidding: BiddingSettings,
+    pub keys: Keys,
+    pub relays: Vec<RelayConfig>,
+}
+
+fn mainnet() -> Network {
+    Network::Mainnet
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuilderSettings {
+    /// the time that a payload job builds for
+    pub deadline_ms: u64,
+    /// the extra data of the payloads that we build
+    #[serde(default)]
+    pub extra_data: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BiddingSettings {
+    /// the names of the algorithms that each payload job runs, in the order that they run
+    pub algorithms: Vec<String>,
+    /// the time after which we give up on the submission to a relay
+    pub submission_timeout_ms: u64,
+    /// we submit bids from this time into the slot that we build in, i.e. the slot prior to the
+    /// slot of the proposal...
+    pub submission_start_ms: u64,
+    /// ...until this time into the slot that we build in
+    pub submission_end_ms: u64,
+}
+
+impl Default for BiddingSettings {
+    fn default() -> Self {
+        Self {
+            algorithms: default_algorithms()
+                .iter()
+                .map(|algorithm| algorithm.name().to_string())
+                .collect(),
+            submission_timeout_ms: 500,
+            submission_start_ms: 10_000,
+            submission_end_ms: 12_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keys {
+    /// the BLS key that signs our bids
+    pub builder: BuilderKey,
+    /// the V3 keystore of the wallet that pays the proposers
+    pub payment_wallet: KeystoreSource,
+}
+
+/// the source of the BLS key of the builder
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase", deny_unknown_fields)]
+pub enum BuilderKey {
+    /// an EIP-2335 keystore
+    Keystore(KeystoreSource),
+    /// a remote signer that holds the key. the signer must speak the Web3Signer signing API and
+    /// support the `BUILDER_BID` request type, which stock Web3Signer does not.
+    Web3Signer {
+        url: String,
+        public_key: BlsPublicKey,
+        #[serde(default = "default_signer_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+fn default_signer_timeout_ms() -> u64 {
+    200
+}
+
+impl BuilderKey {
+    /// returns the signer of the bids of the builder on `network`
+    pub fn signer(&self, network: &Network) -> Result<Arc<dyn BidSigner>> {
+        let signer: Arc<dyn BidSigner> = match self {
+            BuilderKey::Keystore(source) => {
+                Arc::new(BuilderSigner::new(source.bls_secret_key()?, network)?)
+            }
+            BuilderKey::Web3Signer {
+                url,
+                public_key,
+                timeout_ms,
+            } => Arc::new(Web3Signer::new(
+                url,
+                public_key.clone(),
+                network,
+                Duration::from_millis(*timeout_ms),
+            )?),
+        };
+        Ok(signer)
+    }
 }
 
-pub fn get_relay_endpoints() -> Vec<RelayEndpoint> {
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayConfig {
+    pub name: String,
+    pub url: String,
     // TODO somebody doesn't support gzip
-    return vec![
-        RelayEndpoint::new("ultrasound", "https://relay.ultrasound.money", true, None),
-        RelayEndpoint::new(
-            "bloxroute.max-profit",
-            "https://bloxroute.max-profit.blxrbdn.com",
-            true,
-            None,
-        ),
-        RelayEndpoint::new(
-            "bloxroute.regulated",
-            "https://bloxroute.regulated.blxrbdn.com",
-            true,
-            None,
-        ),
-        RelayEndpoint::new("flashbots", "https://boost-relay.flashbots.net", true, None),
-        RelayEndpoint::new("gnosis", "https://agnostic-relay.net", true, None),
-        RelayEndpoint::new(
-            "blocknative",
-            "https://builder-relay-mainnet.blocknative.com",
-            true,
-            None,
-        ),
-        RelayEndpoint::new("aestus", "https://aestus.live", true, None),
-        RelayEndpoint::new("edennetwork", "https://relay.edennetwork.io", true, None),
+    #[serde(default)]
+    pub gzip: bool,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// the value of the authorization header of the submissions to the relay
+    pub auth_header: Option<String>,
+}
+
+impl RelayConfig {
+    pub fn endpoint(&self) -> RelayEndpoint {
         RelayEndpoint::new(
-            "securerpc",
-            "https://mainnet-relay.securerpc.com",
-            true,
-            None,
-        ),
-    ];
+            &self.name,
+            &self.url,
+            self.encoding,
+            self.gzip,
+            self.auth_header.clone(),
+        )
+    }
+}
+
+/// the error of the invalid value of `field`
+fn invalid(field: impl Display, reason: impl Display) -> anyhow::Error {
+    anyhow!("invalid `{field}`: {reason}")
+}
+
+fn validate_url(field: impl Display, url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|err| invalid(&field, err))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(invalid(field, format!("`{url}` is not an HTTP URL")));
+    }
+    Ok(())
+}
+
+impl Config {
+    /// reads and validates the config at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        config
+            .parse()
+            .with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// checks the values that the types of the fields do not
+    pub fn validate(&self) -> Result<()> {
+        if self.builder.deadline_ms == 0 {
+            return Err(invalid("builder.deadline_ms", "must be positive"));
+        }
+        if self.builder.extra_data.len() > MAX_EXTRA_DATA_BYTES {
+            return Err(invalid(
+                "builder.extra_data",
+                format!("longer than {MAX_EXTRA_DATA_BYTES} bytes"),
+            ));
+        }
+
+        if self.bidding.algorithms.is_empty() {
+            return Err(invalid("bidding.algorithms", "no algorithms"));
+        }
+        let known: Vec<_> = default_algorithms()
+            .iter()
+            .map(|algorithm| algorithm.name())
+            .collect();
+        let mut algorithms = HashSet::new();
+        for (i, name) in self.bidding.algorithms.iter().enumerate() {
+            if !known.contains(&name.as_str()) {
+                return Err(invalid(
+                    format!("bidding.algorithms[{i}]"),
+                    format!("unknown algorithm `{name}`, expected one of {known:?}"),
+                ));
+            }
+            if !algorithms.insert(name) {
+                return Err(invalid(
+                    format!("bidding.algorithms[{i}]"),
+                    format!("duplicate algorithm `{name}`"),
+                ));
+            }
+        }
+        if self.bidding.submission_timeout_ms == 0 {
+            return Err(invalid("bidding.submission_timeout_ms", "must be positive"));
+        }
+        if self.bidding.submission_start_ms >= self.bidding.submission_end_ms {
+            return Err(invalid(
+                "bidding.submission_start_ms",
+                "must be less than `bidding.submission_end_ms`",
+            ));
+        }
+
+        if let BuilderKey::Web3Signer { url, .. } = &self.keys.builder {
+            validate_url("keys.builder.url", url)?;
+        }
+
+        if self.relays.is_empty() {
+            return Err(invalid("relays", "no relays"));
+        }
+        let mut names = HashSet::new();
+        for (i, relay) in self.relays.iter().enumerate() {
+            if relay.name.is_empty() {
+                return Err(invalid(format!("relays[{i}].name"), "empty name"));
+            }
+            if !names.insert(&relay.name) {
+                return Err(invalid(
+                    format!("relays[{i}].name"),
+                    format!("duplicate relay `{}`", relay.name),
+                ));
+            }
+            validate_url(format!("relays[{i}].url"), &relay.url)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn relay_endpoints(&self) -> Vec<RelayEndpoint> {
+        self.relays.iter().map(RelayConfig::endpoint).collect()
+    }
+
+    pub fn submission_timeout(&self) -> Duration {
+        Duration::from_millis(self.bidding.submission_timeout_ms)
+    }
+
+    /// the time into the slot that we build in during which we submit bids
+    pub fn submission_window(&self) -> Range<Duration> {
+        Duration::from_millis(self.bidding.submission_start_ms)
+            ..Duration::from_millis(self.bidding.submission_end_ms)
+    }
+
+    /// the algorithms of `bidding.algorithms`, in the order of the config
+    pub fn algorithms(&self) -> Vec<Arc<dyn Algorithm>> {
+        let available = default_algorithms();
+        self.bidding
+            .algorithms
+            .iter()
+            .filter_map(|name| {
+                available
+                    .iter()
+                    .find(|algorithm| algorithm.name() == name)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// loads the BLS key of the builder
+    pub fn signer(&self) -> Result<Arc<dyn BidSigner>> {
+        self.keys
+            .builder
+            .signer(&self.network)
+            .context("failed to load `keys.builder`")
+    }
+
+    /// loads the payment wallet, and returns the config of the payload builder
+    pub fn builder_config(&self) -> Result<BuilderConfig> {
+        let wallet = self
+            .keys
+            .payment_wallet
+            .wallet()
+            .context("failed to load `keys.payment_wallet`")?;
+
+        let mut extra_data = [0; MAX_EXTRA_DATA_BYTES];
+        extra_data[..self.builder.extra_data.len()]
+            .copy_from_slice(self.builder.extra_data.as_bytes());
+
+        Ok(BuilderConfig {
+            deadline: Duration::from_millis(self.builder.deadline_ms),
+            extra_data: u128::from_le_bytes(extra_data),
+            wallet,
+            algorithms: self.algorithms(),
+        })
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = anyhow::Error;
+
+    /// parses and validates the TOML config `s`
+    fn from_str(s: &str) -> Result<Self> {
+        let config: Config = toml::from_str(s)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethereum_consensus::crypto::SecretKey;
+
+    const EXAMPLE: &str = include_str!("../evangelion.example.toml");
+
+    const MINIMAL: &str = r#"
+        [builder]
+        deadline_ms = 1000
+
+        [keys.builder]
+        source = "keystore"
+        keystore = "keys/builder.json"
+        password_file = "keys/builder.txt"
+
+        [keys.payment_wallet]
+        keystore = "keys/wallet.json"
+        password_file = "keys/wallet.txt"
+
+        [[relays]]
+        name = "flashbots"
+        url = "https://boost-relay.flashbots.net"
+    "#;
+
+    fn error(config: &str) -> String {
+        config.parse::<Config>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_example_config() {
+        let config: Config = EXAMPLE.parse().unwrap();
+        assert_eq!(config.network, Network::Mainnet);
+        assert_eq!(config.relays.len(), 9);
+        assert!(config.relays.iter().all(|relay| relay.gzip));
+        assert_eq!(config.relay_endpoints()[3].name(), "flashbots");
+    }
+
+    #[test]
+    fn parse_minimal_config() {
+        let config: Config = MINIMAL.parse().unwrap();
+        assert_eq!(config.network, Network::Mainnet);
+        assert_eq!(config.bidding, BiddingSettings::default());
+        assert_eq!(
+            config.relays[0],
+            RelayConfig {
+                name: "flashbots".to_string(),
+                url: "https://boost-relay.flashbots.net".to_string(),
+                gzip: false,
+                encoding: Encoding::Json,
+                auth_header: None,
+            }
+        );
+
+        let config: Config = format!(
+            r#"
+            network = "holesky"
+            {MINIMAL}
+            encoding = "ssz"
+            gzip = true
+            auth_header = "secret"
+            "#
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(config.network, Network::Holesky);
+        assert_eq!(config.relays[0].encoding, Encoding::Ssz);
+        assert_eq!(config.relays[0].auth_header.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn bidding_settings_in_config_order() {
+        let config: Config = format!(
+            "{MINIMAL}\n[bidding]\nalgorithms = [\"interleaved\", \"bundles-first\"]\nsubmission_start_ms = 9000"
+        )
+        .parse()
+        .unwrap();
+        let algorithms: Vec<_> = config
+            .algorithms()
+            .iter()
+            .map(|algorithm| algorithm.name())
+            .collect();
+        assert_eq!(algorithms, ["interleaved", "bundles-first"]);
+        assert_eq!(
+            config.submission_window(),
+            Duration::from_secs(9)..Duration::from_secs(12)
+        );
+    }
+
+    #[test]
+    fn parse_web3signer_key() {
+        let secret_key = SecretKey::random(&mut rand::thread_rng()).unwrap();
+        let public_key = serde_json::to_value(secret_key.public_key()).unwrap();
+        let public_key = public_key.as_str().unwrap();
+        let config: Config = MINIMAL
+            .replace(
+                r#"source = "keystore"
+        keystore = "keys/builder.json"
+        password_file = "keys/builder.txt""#,
+                &format!(
+                    r#"source = "web3signer"
+        url = "http://localhost:9000"
+        public_key = "{public_key}""#
+                ),
+            )
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.keys.builder,
+            BuilderKey::Web3Signer {
+                url: "http://localhost:9000".to_string(),
+                public_key: secret_key.public_key(),
+                timeout_ms: default_signer_timeout_ms(),
+            }
+        );
+
+        let config = MINIMAL.replace(r#"source = "keystore""#, r#"source = "vault""#);
+        assert!(error(&config).contains("vault"));
+    }
+
+    #[test]
+    fn errors_name_offending_field() {
+        let config = MINIMAL.replace("deadline_ms = 1000", "deadline_ms = 0");
+        assert!(error(&config).contains("builder.deadline_ms"));
+
+        let config = MINIMAL.replace(
+            "deadline_ms = 1000",
+            "deadline_ms = 1000\nextra_data = \"way too much extra data\"",
+        );
+        assert!(error(&config).contains("builder.extra_data"));
+
+        let config = format!("{MINIMAL}\n[bidding]\nalgorithms = [\"interleaved\", \"greedy\"]");
+        let err = error(&config);
+        assert!(
+            err.contains("bidding.algorithms[1]") && err.contains("greedy"),
+            "{err}"
+        );
+
+        let config = format!(
+            "{MINIMAL}\n[bidding]\nalgorithms = [\"interleaved\", \"mempool-only\", \"interleaved\"]"
+        );
+        let err = error(&config);
+        assert!(
+            err.contains("bidding.algorithms[2]") && err.contains("duplicate"),
+            "{err}"
+        );
+
+        let config = format!("{MINIMAL}\n[[relays]]\nname = \"flashbots\"\nurl = \"https://a.b\"");
+        assert!(error(&config).contains("relays[1].name"));
+
+        let config = MINIMAL.replace("https://boost-relay.flashbots.net", "boost-relay");
+        assert!(error(&config).contains("relays[0].url"));
+
+        let config = format!("{MINIMAL}\nencoding = \"xml\"");
+        assert!(error(&config).contains("encoding"));
+
+        let config = format!("{MINIMAL}\nurls = \"https://a.b\"");
+        assert!(error(&config).contains("urls"));
+
+        let config = MINIMAL.replace("[[relays]]", "[[relay]]");
+        assert!(error(&config).contains("relay"));
+    }
 }
@@ -1,118 +1,255 @@
-use crate::relay_endpoint::{RelayEndpoint, Validator};
-use crate::signing::sign_builder_message;
-use crate::types::{
-    tx_signed_to_bytes, ExecutionPayload, PayloadAttributes, SignedBidSubmission,
-    WithdrawalMevBoost,
-};
-use anyhow::Result;
-use ethereum_consensus::crypto::SecretKey;
-use ethereum_consensus::primitives::{BlsPublicKey, ExecutionAddress, Hash32};
-use mev_rs::types::BidTrace;
-use reth_primitives::{sign_message, Block, U256};
-use ruint::aliases::B384;
-
-// TODO default signing domain (originally in boost-utils, possibly in mev-rs now?)
-
-struct Config {
-    pub endpoints: Vec<RelayEndpoint>,
-    pub builder_public_key: B384,
-}
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::duties::{ProposerDuties, ReadyRelay, SlotClock};
+use crate::relay_endpoint::{EncodedPayload, RelayEndpoint, SendBlockStatus};
+use crate::signing::BidSigner;
+use crate::types::{BidTrace, BlobsBundle, ExecutionPayload, Fork, PayloadAttributes};
+use anyhow::{bail, Result};
+use ethereum_consensus::primitives::{BlsPublicKey, BlsSignature, ExecutionAddress, Hash32};
+use futures_util::future::join_all;
+use reth_primitives::{Block, BlockHash, ChainSpec, U256};
+use tokio::sync::watch;
 
 // TODO: rename
 struct Coordinator {
-    // TODO singing_domain
-    all_endpoints: Vec<RelayEndpoint>, // TODO shall be immutable...
+    all_endpoints: Arc<Vec<RelayEndpoint>>,
     // TODO had syncer
-    last_slot: u64,
-    ready_relays: Vec<ReadyRelay>,
+    /// the relays that have registered the proposers of upcoming slots
+    duties: Arc<ProposerDuties>,
+    /// the head that we currently build on. in-flight submissions watch it to learn that the head
+    /// they were built on has been abandoned.
+    head: watch::Sender<Head>,
     // TODO beacon_client (not real client, redis connection),
-    builder_public_key: B384,
-    secret_key: SecretKey,
+    /// signs the bids for the network that we build on
+    signer: Arc<dyn BidSigner>,
+    /// determines the fork of the submitted payloads
+    chain: Arc<ChainSpec>,
+    /// the time after which we give up on the submission to a relay
+    submission_timeout: Duration,
+    /// maps the slots of the network to unix time
+    clock: SlotClock,
+    /// the time into the slot that we build in (i.e. the slot prior to the slot of the proposal)
+    /// during which we submit bids
+    submission_window: Range<Duration>,
 }
 
-struct ReadyRelay {
-    pub index: usize,
-    pub validator: Validator,
+/// the slot and parent block that we build on, with the relays that registered its proposer
+#[derive(Debug, Clone, Default)]
+struct Head {
+    slot: u64,
+    head_hash: BlockHash,
+    ready_relays: Arc<Vec<ReadyRelay>>,
 }
 
 // TODO:
 // metric -- what slots we found
 // metric -- how many we send per slot per validator
 
-impl Coordinator {
-    fn get_ready_relays(&self, slot: u64) -> Vec<ReadyRelay> {
-        self.all_endpoints
-            .iter()
-            .enumerate()
-            .filter_map(|(i, endpoint)| match endpoint.get_validators() {
-                Ok(validators) => {
-                    let validator = validators
-                        .into_iter()
-                        .find(|validator| validator.slot == slot);
-                    if validator.is_some() {
-                        Some(ReadyRelay {
-                            index: i,
-                            validator: validator.unwrap(),
-                        })
-                    } else {
-                        None
-                    }
-                }
-                Err(err) => {
-                    println!("GetValidatorForSlot: {}", err);
-                    None
-                }
-            })
-            .collect()
-    }
-
-    fn on_payload_attributes(&mut self, pa: PayloadAttributes) {
-        // TODO: is it reorg? also, cases < and == were treated separately before
-        if pa.slot <= self.last_slot {
-            // TODO: log error? we shall only get new ones, right? Or maybe it's reorg?
-            return;
-        }
+/// the outcome of a block submission to a relay
+#[derive(Debug)]
+pub enum SubmissionOutcome {
+    /// the relay responded to the submission
+    Delivered(SendBlockStatus),
+    /// the submission failed before the relay responded
+    Failed(anyhow::Error),
+    /// the relay did not respond within the submission timeout
+    TimedOut,
+    /// the head that the block was built on changed before the relay responded
+    Abandoned,
+}
 
-        self.last_slot = pa.slot;
+/// the outcome of the submission of a block to a relay
+#[derive(Debug)]
+pub struct RelaySubmission {
+    pub relay: String,
+    pub outcome: SubmissionOutcome,
+}
 
-        // TODO: previously wrote slot metric (???)
-        // TODO: log new payload attributes
+/// the outcomes of the submissions of a block to each of the ready relays of a slot
+#[derive(Debug)]
+pub struct SlotSubmissions {
+    pub slot: u64,
+    pub block_hash: BlockHash,
+    pub submissions: Vec<RelaySubmission>,
+}
 
-        self.ready_relays = self.get_ready_relays(pa.slot);
+/// submits the bid `message` with `signature` for `payload` to `endpoint`, giving up on the relay
+/// after `timeout` or once `head` changes
+async fn submit(
+    endpoint: &RelayEndpoint,
+    message: &BidTrace,
+    signature: &BlsSignature,
+    payload: &EncodedPayload,
+    timeout: Duration,
+    mut head: watch::Receiver<Head>,
+) -> SubmissionOutcome {
+    let submission = tokio::time::timeout(timeout, endpoint.post_bid(message, signature, payload));
+    tokio::select! {
+        outcome = submission => match outcome {
+            Ok(Ok(status)) => SubmissionOutcome::Delivered(status),
+            Ok(Err(err)) => SubmissionOutcome::Failed(err),
+            Err(_) => SubmissionOutcome::TimedOut,
+        },
+        // NOTE: the sender outlives the submission, so `changed` only returns on a new head
+        _ = head.changed() => SubmissionOutcome::Abandoned,
     }
+}
 
-    fn on_new_block(&self, block: Block, value: U256) {
-        self.ready_relays.iter().for_each(|relay| {
-            // TODO may be slow to rebuild it for each relay. Execution payload is always the same
-            let bid: SignedBidSubmission = self.create_bid(relay, &block, value).unwrap();
-            self.all_endpoints[relay.index].post_block(&bid);
+impl Coordinator {
+    /// a coordinator for the relays and the keys of `config`, which starts to refresh the proposer
+    /// duties of the relays. must be called within a tokio runtime.
+    fn new(config: &Config, chain: Arc<ChainSpec>) -> Result<Self> {
+        let signer = config.signer()?;
+        let all_endpoints = Arc::new(config.relay_endpoints());
+        let context = config.network.context()?;
+        let clock = SlotClock::new(&config.network, &context);
+        let duties = Arc::new(ProposerDuties::new(Arc::new(context)));
+        // NOTE: the duties are refreshed in the background for as long as the process runs
+        Arc::clone(&duties).spawn(Arc::clone(&all_endpoints), clock);
+
+        Ok(Self {
+            all_endpoints,
+            duties,
+            head: watch::channel(Head::default()).0,
+            signer,
+            chain,
+            submission_timeout: config.submission_timeout(),
+            clock,
+            submission_window: config.submission_window(),
         })
     }
 
-    // TODO check if all fields are correct
-    fn create_bid(
+    /// moves to the head of `pa`, unless `pa` is for a past slot or for the current head. if the
+    /// head of the current slot changes (e.g. on a reorg), then we abandon the submissions that are
+    /// in flight for the previous head.
+    fn on_payload_attributes(&self, pa: PayloadAttributes) {
+        self.head.send_if_modified(|head| {
+            if pa.slot < head.slot || (pa.slot == head.slot && pa.head_hash == head.head_hash) {
+                return false;
+            }
+
+            if pa.slot == head.slot {
+                tracing::info!(
+                    slot = pa.slot,
+                    previous = ?head.head_hash,
+                    head = ?pa.head_hash,
+                    "head changed within slot"
+                );
+            }
+
+            // TODO: previously wrote slot metric (???)
+            // NOTE: the duties are cached, so re-resolving the relays on each change is cheap
+            *head = Head {
+                slot: pa.slot,
+                head_hash: pa.head_hash,
+                ready_relays: Arc::new(self.duties.ready_relays(pa.slot)),
+            };
+            true
+        });
+    }
+
+    /// returns whether we submit bids for the proposal of `slot` at unix time `now`
+    fn in_submission_window(&self, slot: u64, now: Duration) -> bool {
+        self.clock
+            .since_slot_start(slot.saturating_sub(1), now)
+            .map_or(false, |elapsed| self.submission_window.contains(&elapsed))
+    }
+
+    /// submits `block` to all of the ready relays concurrently. `block` must be built on the
+    /// current head within the submission window, and its submissions are abandoned if the head
+    /// changes before they complete.
+    async fn on_new_block(
         &self,
-        relay: &ReadyRelay,
-        block: &Block,
+        block: Block,
         value: U256,
-    ) -> Result<SignedBidSubmission> {
+        blobs_bundle: BlobsBundle,
+    ) -> Result<SlotSubmissions> {
         let block_hash = block.hash_slow();
 
-        // parent_hash_bytevector = block.parent_hash.as_bytes()
+        let mut heads = self.head.subscribe();
+        let head = heads.borrow_and_update().clone();
+        if block.parent_hash != head.head_hash {
+            bail!(
+                "block {block_hash:?} is built on {:?} rather than the head {:?}",
+                block.parent_hash,
+                head.head_hash
+            );
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        if !self.in_submission_window(head.slot, now) {
+            bail!(
+                "block {block_hash:?} is outside of the submission window of slot {}",
+                head.slot
+            );
+        }
 
-        let parent_hash = Hash32::try_from(block.parent_hash.as_bytes());
+        // the execution payload is the same for all relays, so we encode it once in each of the
+        // formats of the ready relays. only the bid and its signature differ per relay.
+        let fork = Fork::at(&self.chain, block.timestamp);
+        let execution_payload = ExecutionPayload::new(fork, &block, block_hash)?;
+        let formats = head
+            .ready_relays
+            .iter()
+            .map(|relay| self.all_endpoints[relay.index].format());
+        let payload = EncodedPayload::new(&execution_payload, &blobs_bundle, formats)?;
+
+        let submissions = head.ready_relays.iter().map(|relay| {
+            let payload = &payload;
+            let heads = heads.clone();
+            async move {
+                let endpoint = &self.all_endpoints[relay.index];
+                let bid = self.create_bid(head.slot, relay, &block, block_hash, value);
+                let outcome = match bid.await {
+                    Ok((message, signature)) => {
+                        submit(
+                            endpoint,
+                            &message,
+                            &signature,
+                            payload,
+                            self.submission_timeout,
+                            heads,
+                        )
+                        .await
+                    }
+                    Err(err) => SubmissionOutcome::Failed(err),
+                };
+                RelaySubmission {
+                    relay: endpoint.name().to_string(),
+                    outcome,
+                }
+            }
+        });
 
-        let pk_bytes: [u8; 48] = self.builder_public_key.to_le_bytes();
-        let pk_slice = &pk_bytes[..];
+        Ok(SlotSubmissions {
+            slot: head.slot,
+            block_hash,
+            submissions: join_all(submissions).await,
+        })
+    }
 
-        let propeser_pk_bytes: [u8; 48] = relay.validator.entry.message.pubkey.to_le_bytes();
+    /// returns the signed bid for `block` with hash `block_hash` to the proposer of `slot` at `relay`
+    // TODO check if all fields are correct
+    async fn create_bid(
+        &self,
+        slot: u64,
+        relay: &ReadyRelay,
+        block: &Block,
+        block_hash: BlockHash,
+        value: U256,
+    ) -> Result<(BidTrace, BlsSignature)> {
+        // NOTE: the registration holds the public key as a big-endian integer, so its big-endian
+        // bytes are the bytes of the key
+        let propeser_pk_bytes: [u8; 48] = relay.validator.entry.message.pubkey.to_be_bytes();
         let proposer_pk_slice = &propeser_pk_bytes[..];
 
         let mut message = BidTrace {
-            slot: self.last_slot,
+            slot,
             parent_hash: Hash32::try_from(block.parent_hash.as_bytes())?,
             block_hash: Hash32::try_from(block_hash.as_bytes())?,
-            builder_public_key: BlsPublicKey::try_from(pk_slice)?,
+            builder_public_key: self.signer.public_key().clone(),
             proposer_public_key: BlsPublicKey::try_from(proposer_pk_slice)?,
             proposer_fee_recipient: ExecutionAddress::try_from(
                 relay.validator.entry.message.fee_recipient.as_bytes(),
@@ -122,69 +259,211 @@ impl Coordinator {
             value: ssz_rs::U256::from_bytes_le(value.to_le_bytes()),
         };
 
-        let execution_payload = ExecutionPayload {
-            parent_hash: block.parent_hash,
-            fee_recipient: block.beneficiary,
-            state_root: block.state_root,
-            receipts_root: block.receipts_root,
-            logs_bloom: block.logs_bloom,
-            prev_randao: block.mix_hash,
-            block_number: block.number,
-            gas_limit: block.gas_limit,
-            gas_used: block.gas_used,
-            timestamp: block.timestamp,
-            extra_data: block.extra_data.clone(), // TODO
-            base_fee_per_gas: block.base_fee_per_gas.unwrap().into(),
-            block_hash: block_hash,
-            transactions: block
-                .body
-                .clone()
-                .into_iter()
-                .map(tx_signed_to_bytes)
-                .collect(),
-            withdrawals: block
-                .withdrawals
-                .clone()
-                .unwrap()
-                .into_iter()
-                .map(WithdrawalMevBoost::from)
-                .collect(),
-            // data_gas_used: block.blob_gas_used,
-            // excess_data_gas: block.excess_blob_gas,
-        };
-
-        // TODO ???
-
-        let signature = sign_builder_message(&mut message, &self.secret_key)?;
+        let signature = self.signer.sign_bid(&mut message).await?;
 
-        return Ok(SignedBidSubmission {
-            message: message,
-            signature: signature,
-            execution_payload: execution_payload,
-        });
+        Ok((message, signature))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use reth_revm_primitives::new;
-
-    use crate::config::get_relay_endpoints;
+    use crate::mev_boost_relay_json::{GET_VALIDATORS_JSON, SEND_BLOCK_REQUEST_EXAMPLE_JSON};
+    use crate::relay_endpoint::{Encoding, Format, Validator};
+    use crate::signing::{BuilderSigner, Network};
+    use crate::types::SignedBidSubmission;
+    use ethereum_consensus::crypto::SecretKey;
+    use reth_primitives::{Address, H256};
 
     use super::*;
 
+    use crate::test_utils::{http_response, mock_server};
+
+    fn coordinator() -> Coordinator {
+        let secret_key = SecretKey::random(&mut rand::thread_rng()).unwrap();
+        let signer = BuilderSigner::new(secret_key, &Network::Mainnet).unwrap();
+        let clock = SlotClock::new(&Network::Mainnet, signer.context());
+
+        Coordinator {
+            all_endpoints: Arc::default(),
+            duties: Arc::new(ProposerDuties::new(signer.context().clone())),
+            head: watch::channel(Head::default()).0,
+            signer: Arc::new(signer),
+            chain: Arc::new(reth_primitives::MAINNET.clone()),
+            submission_timeout: Duration::from_millis(500),
+            clock,
+            submission_window: Duration::ZERO..Duration::from_secs(12),
+        }
+    }
+
+    fn payload_attributes(slot: u64, head_hash: BlockHash) -> PayloadAttributes {
+        PayloadAttributes {
+            timestamp: 0,
+            random: H256::zero(),
+            suggested_fee_receiptient: Address::zero(),
+            withdrawals: vec![],
+            slot,
+            head_hash,
+            gas_limit: 30_000_000,
+        }
+    }
+
     #[test]
-    fn create_coordinator() {
-        let builder_pk = B384::default();
-        let builder_sk = SecretKey::default();
-
-        let coordinator = Coordinator {
-            all_endpoints: get_relay_endpoints(),
-            last_slot: 0,
-            ready_relays: vec![],
-            builder_public_key: builder_pk,
-            secret_key: builder_sk,
+    fn on_payload_attributes_follows_head() {
+        let coordinator = coordinator();
+        let mut heads = coordinator.head.subscribe();
+
+        let first = H256::repeat_byte(1);
+        coordinator.on_payload_attributes(payload_attributes(2, first));
+        assert!(heads.has_changed().unwrap());
+        assert_eq!(heads.borrow_and_update().slot, 2);
+
+        // attributes for the current head or a past slot are ignored
+        coordinator.on_payload_attributes(payload_attributes(2, first));
+        coordinator.on_payload_attributes(payload_attributes(1, H256::repeat_byte(2)));
+        assert!(!heads.has_changed().unwrap());
+
+        // a new head within the slot replaces the head
+        let second = H256::repeat_byte(3);
+        coordinator.on_payload_attributes(payload_attributes(2, second));
+        assert!(heads.has_changed().unwrap());
+        let head = heads.borrow_and_update().clone();
+        assert_eq!((head.slot, head.head_hash), (2, second));
+    }
+
+    #[test]
+    fn submission_window_precedes_proposal() {
+        let mut coordinator = coordinator();
+        let slot = 100;
+        let now = Duration::from_secs(coordinator.clock.slot_start(slot - 1) + 1);
+        assert!(coordinator.in_submission_window(slot, now));
+        assert!(!coordinator.in_submission_window(slot + 1, now));
+
+        coordinator.submission_window = Duration::from_secs(12)..Duration::from_secs(24);
+        assert!(!coordinator.in_submission_window(slot, now));
+    }
+
+    #[tokio::test]
+    async fn bid_names_proposer_of_registration() {
+        let coordinator = coordinator();
+        let validators: Vec<Validator> = serde_json::from_str(GET_VALIDATORS_JSON).unwrap();
+        let relay = ReadyRelay {
+            index: 0,
+            validator: validators[0].clone(),
+        };
+
+        let (bid, _) = coordinator
+            .create_bid(1, &relay, &Block::default(), BlockHash::zero(), U256::ZERO)
+            .await
+            .unwrap();
+
+        // the public key keeps the byte order of the registration
+        assert_eq!(
+            serde_json::to_value(&bid.proposer_public_key).unwrap(),
+            "0x93247f2209abcacf57b75a51dafae777f9dd38bc7053d1af526f220a7489a6d3a2753e5f3e8b1cfe39b56f43611df74a"
+        );
+    }
+
+    #[tokio::test]
+    async fn on_new_block_rejects_block_off_head() {
+        let coordinator = coordinator();
+        coordinator.on_payload_attributes(payload_attributes(1, H256::repeat_byte(1)));
+
+        let block = Block::default();
+        let result = coordinator
+            .on_new_block(block, U256::ZERO, BlobsBundle::default())
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn submit_reports_outcome_of_relay() {
+        let bid: SignedBidSubmission =
+            serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON).unwrap();
+        let (message, execution_payload, blobs_bundle, signature) = bid.into_parts();
+        let formats = [false, true].into_iter().flat_map(|gzip| {
+            [Encoding::Json, Encoding::Ssz].map(|encoding| Format { encoding, gzip })
+        });
+        let payload = EncodedPayload::new(&execution_payload, &blobs_bundle, formats).unwrap();
+        let timeout = Duration::from_millis(200);
+        let (head, _) = watch::channel(Head::default());
+
+        let accepting = mock_server(http_response("200 OK", ""), Duration::ZERO).await;
+        let endpoint = RelayEndpoint::new("accepting", &accepting, Encoding::Json, true, None);
+        assert!(matches!(
+            submit(
+                &endpoint,
+                &message,
+                &signature,
+                &payload,
+                timeout,
+                head.subscribe()
+            )
+            .await,
+            SubmissionOutcome::Delivered(SendBlockStatus { code: 200, .. })
+        ));
+
+        let body = r#"{"code":400,"message":"submission for past slot"}"#;
+        let rejecting = mock_server(http_response("400 Bad Request", body), Duration::ZERO).await;
+        let endpoint = RelayEndpoint::new("rejecting", &rejecting, Encoding::Ssz, false, None);
+        match submit(
+            &endpoint,
+            &message,
+            &signature,
+            &payload,
+            timeout,
+            head.subscribe(),
+        )
+        .await
+        {
+            SubmissionOutcome::Delivered(status) => {
+                assert_eq!(status.code, 400);
+                assert_eq!(status.message, "submission for past slot");
+            }
+            outcome => panic!("unexpected outcome {outcome:?}"),
+        }
+
+        let slow = mock_server(http_response("200 OK", ""), Duration::from_secs(10)).await;
+        let endpoint = RelayEndpoint::new("slow", &slow, Encoding::Json, false, None);
+        assert!(matches!(
+            submit(
+                &endpoint,
+                &message,
+                &signature,
+                &payload,
+                timeout,
+                head.subscribe()
+            )
+            .await,
+            SubmissionOutcome::TimedOut
+        ));
+
+        let abandoned = mock_server(http_response("200 OK", ""), Duration::from_secs(10)).await;
+        let endpoint = RelayEndpoint::new("abandoned", &abandoned, Encoding::Json, false, None);
+        let receiver = head.subscribe();
+        let new_head = async {
+            sleep(Duration::from_millis(50)).await;
+            head.send_modify(|head| head.slot += 1);
         };
+        let (outcome, _) = tokio::join!(
+            submit(&endpoint, &message, &signature, &payload, timeout, receiver),
+            new_head
+        );
+        assert!(matches!(outcome, SubmissionOutcome::Abandoned));
+
+        let garbage = mock_server(http_response("200 OK", "garbage"), Duration::ZERO).await;
+        let endpoint = RelayEndpoint::new("garbage", &garbage, Encoding::Json, false, None);
+        assert!(matches!(
+            submit(
+                &endpoint,
+                &message,
+                &signature,
+                &payload,
+                timeout,
+                head.subscribe()
+            )
+            .await,
+            SubmissionOutcome::Failed(_)
+        ));
     }
 
     // #[test]
@@ -0,0 +1,427 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::relay_endpoint::{Entry, RelayEndpoint, Validator};
+use crate::signing::Network;
+
+use anyhow::Result;
+use ethereum_consensus::state_transition::Context;
+use futures_util::future::join_all;
+use tokio::task::{self, JoinHandle};
+
+/// maps unix time to beacon chain slots and epochs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotClock {
+    pub genesis_time: u64,
+    pub seconds_per_slot: u64,
+    pub slots_per_epoch: u64,
+}
+
+impl SlotClock {
+    /// the clock of the network with the consensus spec `context` and the genesis time of `network`
+    pub fn new(network: &Network, context: &Context) -> Self {
+        Self {
+            genesis_time: network.genesis_time(),
+            seconds_per_slot: context.seconds_per_slot,
+            slots_per_epoch: context.slots_per_epoch,
+        }
+    }
+
+    /// the slot at unix time `timestamp`. the slot prior to genesis is zero.
+    pub fn slot_at(&self, timestamp: u64) -> u64 {
+        timestamp.saturating_sub(self.genesis_time) / self.seconds_per_slot
+    }
+
+    pub fn current_slot(&self) -> u64 {
+        self.slot_at(now())
+    }
+
+    pub fn epoch(&self, slot: u64) -> u64 {
+        slot / self.slots_per_epoch
+    }
+
+    /// the unix time at which `slot` starts
+    pub fn slot_start(&self, slot: u64) -> u64 {
+        self.genesis_time + slot * self.seconds_per_slot
+    }
+
+    /// the unix time at which `epoch` starts
+    pub fn epoch_start(&self, epoch: u64) -> u64 {
+        self.slot_start(epoch * self.slots_per_epoch)
+    }
+
+    /// the time elapsed between the start of `slot` and unix time `now`, or `None` if `slot` has
+    /// not started by `now`
+    pub fn since_slot_start(&self, slot: u64, now: Duration) -> Option<Duration> {
+        now.checked_sub(Duration::from_secs(self.slot_start(slot)))
+    }
+
+    /// the time remaining until the start of `slot`, which is zero if `slot` already started
+    pub fn until_slot(&self, slot: u64) -> Duration {
+        Duration::from_secs(self.slot_start(slot).saturating_sub(now()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// a relay that has registered the proposer of a slot
+#[derive(Debug, Clone)]
+pub struct ReadyRelay {
+    /// the index of the relay among the relay endpoints
+    pub index: usize,
+    pub validator: Validator,
+}
+
+/// the proposer duties of the relays, indexed by slot
+///
+/// relays serve the duties of the current and the next epoch, so we refresh the duties once per
+/// epoch in the background, rather than query the relays on the hot path of each slot.
+#[derive(Debug)]
+pub struct ProposerDuties {
+    duties: RwLock<BTreeMap<u64, Vec<ReadyRelay>>>,
+    /// whether the signatures of the validator registrations that the relays serve are valid.
+    /// relays serve the same registrations on every refresh, so we verify each of them once.
+    ///
+    /// NOTE: the signature covers the entire registration, so the cache is keyed by the entire
+    /// registration rather than by the public key and the timestamp alone.
+    registrations: Mutex<HashMap<Entry, bool>>,
+    /// the consensus spec of the network that the validators registered on
+    context: Arc<Context>,
+}
+
+impl ProposerDuties {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            duties: Default::default(),
+            registrations: Default::default(),
+            context,
+        }
+    }
+
+    /// returns the relays that have registered the proposer of `slot`
+    pub fn ready_relays(&self, slot: u64) -> Vec<ReadyRelay> {
+        self.duties
+            .read()
+            .unwrap()
+            .get(&slot)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// spawns a task that refreshes the duties of all of `endpoints` at the start of every epoch.
+    /// the relays that we failed to fetch the duties of are retried at the start of every slot in
+    /// between.
+    pub fn spawn(
+        self: Arc<Self>,
+        endpoints: Arc<Vec<RelayEndpoint>>,
+        clock: SlotClock,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let all: Vec<usize> = (0..endpoints.len()).collect();
+            let mut failed = Vec::new();
+            let mut next_refresh = 0;
+            loop {
+                let slot = clock.current_slot();
+                if slot >= next_refresh {
+                    failed = self.refresh(&endpoints, &all, &clock).await;
+                    next_refresh = (clock.epoch(slot) + 1) * clock.slots_per_epoch;
+                } else if !failed.is_empty() {
+                    failed = self.refresh(&endpoints, &failed, &clock).await;
+                }
+
+                let wake = if failed.is_empty() {
+                    next_refresh
+                } else {
+                    slot + 1
+                };
+                tokio::time::sleep(clock.until_slot(wake)).await;
+            }
+        })
+    }
+
+    /// fetches the duties of the relays at `indices` among `endpoints` concurrently, and drops
+    /// the duties whose validator registrations are not signed by the proposer. returns the
+    /// indices of the relays that we failed to fetch the duties of.
+    pub async fn refresh(
+        &self,
+        endpoints: &[RelayEndpoint],
+        indices: &[usize],
+        clock: &SlotClock,
+    ) -> Vec<usize> {
+        let validators = join_all(
+            indices
+                .iter()
+                .map(|&index| endpoints[index].get_validators()),
+        )
+        .await;
+        let validators: Vec<_> = indices.iter().copied().zip(validators).collect();
+        let failed = validators
+            .iter()
+            .filter(|(_, validators)| validators.is_err())
+            .map(|(index, _)| *index)
+            .collect();
+
+        // NOTE: BLS verification is slow, so we verify the registrations off the async executor and
+        // before we take the lock on the duties
+        let served: HashSet<&Entry> = validators
+            .iter()
+            .filter_map(|(_, validators)| validators.as_ref().ok())
+            .flatten()
+            .map(|validator| &validator.entry)
+            .collect();
+        let unverified: Vec<Entry> = {
+            let registrations = self.registrations.lock().unwrap();
+            served
+                .iter()
+                .filter(|entry| !registrations.contains_key(*entry))
+                .map(|entry| (*entry).clone())
+                .collect()
+        };
+        let outcomes = self.verify(unverified).await;
+
+        let validators: Vec<_> = {
+            let mut registrations = self.registrations.lock().unwrap();
+            registrations.extend(outcomes);
+            // forget the registrations that no relay serves anymore
+            if indices.len() == endpoints.len() {
+                registrations.retain(|entry, _| served.contains(&entry));
+            }
+
+            validators
+                .into_iter()
+                .map(|(index, validators)| {
+                    let validators = validators.map(|validators| {
+                        validators
+                            .into_iter()
+                            .filter(|validator| registrations.get(&validator.entry) == Some(&true))
+                            .collect()
+                    });
+                    (index, validators)
+                })
+                .collect()
+        };
+
+        let first_slot = clock.epoch(clock.current_slot()) * clock.slots_per_epoch;
+        self.update(validators, first_slot);
+        failed
+    }
+
+    /// replaces the duties of each relay with the validators it served, and removes the duties of
+    /// all slots prior to `first_slot`. if we failed to fetch the duties of a relay, then we keep
+    /// the duties we have for it.
+    fn update<I>(&self, validators: I, first_slot: u64)
+    where
+        I: IntoIterator<Item = (usize, Result<Vec<Validator>>)>,
+    {
+        let mut duties = self.duties.write().unwrap();
+        *duties = duties.split_off(&first_slot);
+
+        for (index, validators) in validators {
+            let validators = match validators {
+                Ok(validators) => validators,
+                Err(err) => {
+                    tracing::warn!(relay = index, %err, "failed to fetch proposer duties");
+                    continue;
+                }
+            };
+
+            for relays in duties.values_mut() {
+                relays.retain(|relay| relay.index != index);
+            }
+            for validator in validators {
+                if validator.slot < first_slot {
+                    continue;
+                }
+                // NOTE: a slot has a single proposer, so we keep the first validator of each slot
+                let relays = duties.entry(validator.slot).or_default();
+                if relays.iter().all(|relay| relay.index != index) {
+                    relays.push(ReadyRelay { index, validator });
+                }
+            }
+        }
+
+        duties.retain(|_, relays| !relays.is_empty());
+    }
+
+    /// verifies that the proposers signed `registrations` on the blocking thread pool, and returns
+    /// whether each of the registrations is valid
+    async fn verify(&self, registrations: Vec<Entry>) -> Vec<(Entry, bool)> {
+        if registrations.is_empty() {
+            return Vec::new();
+        }
+
+        let context = Arc::clone(&self.context);
+        let verification = task::spawn_blocking(move || {
+            registrations
+                .into_iter()
+                .map(|entry| {
+                    let valid = match entry.verify(&context) {
+                        Ok(()) => true,
+                        Err(err) => {
+                            tracing::warn!(
+                                pubkey = ?entry.message.pubkey,
+                                %err,
+                                "rejected validator registration"
+                            );
+                            false
+                        }
+                    };
+                    (entry, valid)
+                })
+                .collect()
+        });
+        verification.await.unwrap_or_else(|err| {
+            tracing::error!(%err, "failed to verify validator registrations");
+            Vec::new()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mev_boost_relay_json::GET_VALIDATORS_JSON;
+    use crate::relay_endpoint::Encoding;
+    use crate::signing::sign_builder_message;
+    use crate::test_utils::{http_response, mock_server};
+
+    use anyhow::anyhow;
+    use ethereum_consensus::{
+        builder::ValidatorRegistration, crypto::SecretKey, primitives::ExecutionAddress,
+    };
+
+    fn validators() -> Vec<Validator> {
+        serde_json::from_str(GET_VALIDATORS_JSON).unwrap()
+    }
+
+    #[test]
+    fn slot_clock() {
+        let clock = SlotClock {
+            genesis_time: 100,
+            seconds_per_slot: 12,
+            slots_per_epoch: 32,
+        };
+        assert_eq!(clock.slot_at(0), 0);
+        assert_eq!(clock.slot_at(111), 0);
+        assert_eq!(clock.slot_at(112), 1);
+        assert_eq!(clock.epoch(31), 0);
+        assert_eq!(clock.epoch(32), 1);
+        assert_eq!(clock.slot_start(2), 100 + 2 * 12);
+        assert_eq!(clock.epoch_start(1), 100 + 32 * 12);
+        assert_eq!(clock.until_slot(0), Duration::ZERO);
+
+        // the clock follows the spec of the network
+        let context = Context::for_goerli();
+        let clock = SlotClock::new(&Network::Goerli, &context);
+        assert_eq!(clock.genesis_time, 1616508000);
+        assert_eq!(clock.seconds_per_slot, 12);
+        assert_eq!(clock.slots_per_epoch, 32);
+    }
+
+    #[tokio::test]
+    async fn refresh_reports_failed_relays() {
+        let serving = mock_server(http_response("200 OK", "[]"), Duration::ZERO).await;
+        let failing = mock_server(
+            http_response("500 Internal Server Error", ""),
+            Duration::ZERO,
+        )
+        .await;
+        let endpoints = [
+            RelayEndpoint::new("serving", &serving, Encoding::Json, false, None),
+            RelayEndpoint::new("failing", &failing, Encoding::Json, false, None),
+        ];
+        let context = Context::for_mainnet();
+        let clock = SlotClock::new(&Network::Mainnet, &context);
+        let duties = ProposerDuties::new(Arc::new(context));
+
+        assert_eq!(duties.refresh(&endpoints, &[0, 1], &clock).await, vec![1]);
+        // a retry only queries the failed relays
+        assert_eq!(duties.refresh(&endpoints, &[1], &clock).await, vec![1]);
+        assert!(duties.refresh(&endpoints, &[0], &clock).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refresh_verifies_each_registration_once() {
+        let context = Context::for_mainnet();
+        let clock = SlotClock::new(&Network::Mainnet, &context);
+        let slot = clock.current_slot() + 1;
+
+        let secret_key = SecretKey::random(&mut rand::thread_rng()).unwrap();
+        let public_key = secret_key.public_key();
+        let mut registration = ValidatorRegistration {
+            fee_recipient: ExecutionAddress::try_from([1; 20].as_slice()).unwrap(),
+            gas_limit: 30_000_000,
+            timestamp: 1_700_000_000,
+            public_key: public_key.clone(),
+        };
+        let signature = sign_builder_message(&mut registration, &secret_key, &context).unwrap();
+        let validator = |slot: u64, fee_recipient: &str| {
+            serde_json::json!({
+                "slot": slot.to_string(),
+                "validator_index": "1",
+                "entry": {
+                    "message": {
+                        "fee_recipient": fee_recipient,
+                        "gas_limit": "30000000",
+                        "timestamp": "1700000000",
+                        "pubkey": public_key,
+                    },
+                    "signature": signature,
+                },
+            })
+        };
+        // the relays serve a registration of the proposer, and one with a forged fee recipient
+        let body = serde_json::json!([
+            validator(slot, &format!("0x{}", "01".repeat(20))),
+            validator(slot + 1, &format!("0x{}", "02".repeat(20))),
+        ])
+        .to_string();
+
+        let first = mock_server(http_response("200 OK", &body), Duration::ZERO).await;
+        let second = mock_server(http_response("200 OK", &body), Duration::ZERO).await;
+        let endpoints = [
+            RelayEndpoint::new("first", &first, Encoding::Json, false, None),
+            RelayEndpoint::new("second", &second, Encoding::Json, false, None),
+        ];
+        let duties = ProposerDuties::new(Arc::new(context));
+
+        assert!(duties.refresh(&endpoints, &[0, 1], &clock).await.is_empty());
+        assert_eq!(duties.ready_relays(slot).len(), 2);
+        assert!(duties.ready_relays(slot + 1).is_empty());
+
+        // both relays serve the same two registrations, whose outcomes are cached
+        let registrations = duties.registrations.lock().unwrap().clone();
+        assert_eq!(registrations.len(), 2);
+        assert_eq!(registrations.values().filter(|valid| **valid).count(), 1);
+    }
+
+    #[test]
+    fn update_indexes_duties_by_slot() {
+        let duties = ProposerDuties::new(Arc::new(Context::for_mainnet()));
+        duties.update([(0, Ok(validators())), (1, Ok(validators()))], 0);
+
+        // the example serves two validators for slot 1, but each relay is ready once
+        let ready = duties.ready_relays(1);
+        assert_eq!(ready.len(), 2);
+        assert_eq!(duties.ready_relays(2).len(), 2);
+        assert!(duties.ready_relays(3).is_empty());
+
+        // a failed relay keeps its duties, while a relay that serves no duties loses them
+        duties.update([(0, Err(anyhow!("unavailable"))), (1, Ok(vec![]))], 0);
+        let ready = duties.ready_relays(2);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].index, 0);
+
+        // the duties of past slots are pruned
+        duties.update([], 2);
+        assert!(duties.ready_relays(1).is_empty());
+        assert_eq!(duties.ready_relays(2).len(), 1);
+    }
+}
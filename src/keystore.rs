@@ -0,0 +1,280 @@
+//! keystores of the builder keys
+//!
+//! NOTE: we decrypt EIP-2335 (i.e. BLS) keystores ourselves. `eth_keystore`, which ethers uses for
+//! the wallet, only reads V3 keystores: those carry a keccak-256 MAC rather than a sha-256
+//! checksum, lay out the KDF parameters differently, and do not normalize the password. nor does
+//! `ethereum-consensus` decrypt keystores. the KDF and cipher crates that we use are the versions
+//! that `eth_keystore` already depends on, and `unicode-normalization` already comes in through
+//! `idna`, so the decryption adds no crates to the build.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use ethereum_consensus::crypto::SecretKey;
+use ethereum_consensus::primitives::BlsPublicKey;
+use ethers::signers::LocalWallet;
+use hmac::Hmac;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// an encrypted key, along with the file that holds the password of the key
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeystoreSource {
+    pub keystore: PathBuf,
+    pub password_file: PathBuf,
+}
+
+impl KeystoreSource {
+    /// decrypts the BLS secret key in the EIP-2335 keystore
+    pub fn bls_secret_key(&self) -> Result<SecretKey> {
+        let keystore = fs::read_to_string(&self.keystore)
+            .with_context(|| format!("failed to read keystore {}", self.keystore.display()))?;
+        let keystore: Keystore = serde_json::from_str(&keystore)
+            .with_context(|| format!("invalid keystore {}", self.keystore.display()))?;
+        keystore
+            .decrypt(&read_password(&self.password_file)?)
+            .with_context(|| format!("failed to decrypt keystore {}", self.keystore.display()))
+    }
+
+    /// decrypts the wallet in the V3 (i.e. execution layer) keystore
+    pub fn wallet(&self) -> Result<LocalWallet> {
+        let password = read_password(&self.password_file)?;
+        LocalWallet::decrypt_keystore(&self.keystore, password)
+            .with_context(|| format!("failed to decrypt keystore {}", self.keystore.display()))
+    }
+}
+
+/// reads the password in `path`, without the line break that commonly ends the file
+fn read_password(path: &Path) -> Result<String> {
+    let password = fs::read_to_string(path)
+        .with_context(|| format!("failed to read password file {}", path.display()))?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// an EIP-2335 BLS keystore
+#[derive(Debug, Clone, Deserialize)]
+pub struct Keystore {
+    crypto: Crypto,
+    pubkey: String,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Crypto {
+    kdf: Module<Kdf>,
+    checksum: Module<Checksum>,
+    cipher: Module<Cipher>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Module<T> {
+    #[serde(flatten)]
+    function: T,
+    message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "function", content = "params", rename_all = "lowercase")]
+enum Kdf {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        r: u32,
+        p: u32,
+        salt: String,
+    },
+    Pbkdf2 {
+        dklen: usize,
+        c: u32,
+        prf: String,
+        salt: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "function", content = "params", rename_all = "lowercase")]
+enum Checksum {
+    Sha256 {},
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "function", content = "params", rename_all = "kebab-case")]
+enum Cipher {
+    Aes128Ctr { iv: String },
+}
+
+impl Keystore {
+    /// decrypts the secret key with `password`
+    pub fn decrypt(&self, password: &str) -> Result<SecretKey> {
+        ensure!(
+            self.version == 4,
+            "unsupported keystore version {}",
+            self.version
+        );
+
+        let key = self.derive_key(&normalize(password))?;
+        ensure!(
+            key.len() >= 32,
+            "derived key of {} bytes is too short",
+            key.len()
+        );
+
+        let mut secret = hex::decode(&self.crypto.cipher.message)?;
+        // NOTE: the checksum and the cipher functions only have a single variant each
+        let checksum = Sha256::new()
+            .chain_update(&key[16..32])
+            .chain_update(&secret)
+            .finalize();
+        if hex::decode(&self.crypto.checksum.message)? != checksum.as_slice() {
+            bail!("invalid password");
+        }
+
+        let Cipher::Aes128Ctr { iv } = &self.crypto.cipher.function;
+        Aes128Ctr::new_from_slices(&key[..16], &hex::decode(iv)?)
+            .map_err(|_| anyhow!("invalid cipher iv"))?
+            .apply_keystream(&mut secret);
+
+        let secret_key = SecretKey::try_from(secret.as_slice())?;
+        let pubkey = hex::decode(self.pubkey.trim_start_matches("0x"))?;
+        let pubkey = BlsPublicKey::try_from(pubkey.as_slice())?;
+        ensure!(
+            secret_key.public_key() == pubkey,
+            "secret key does not match the public key of the keystore"
+        );
+        Ok(secret_key)
+    }
+
+    fn derive_key(&self, password: &[u8]) -> Result<Vec<u8>> {
+        let key = match &self.crypto.kdf.function {
+            Kdf::Scrypt {
+                dklen,
+                n,
+                r,
+                p,
+                salt,
+            } => {
+                ensure!(
+                    n.is_power_of_two(),
+                    "scrypt parameter n must be a power of two"
+                );
+                let params = scrypt::Params::new(n.trailing_zeros() as u8, *r, *p)
+                    .map_err(|err| anyhow!("invalid scrypt parameters: {err}"))?;
+                let mut key = vec![0; *dklen];
+                scrypt::scrypt(password, &hex::decode(salt)?, &params, &mut key)
+                    .map_err(|err| anyhow!("invalid scrypt key length: {err}"))?;
+                key
+            }
+            Kdf::Pbkdf2 {
+                dklen,
+                c,
+                prf,
+                salt,
+            } => {
+                ensure!(prf == "hmac-sha256", "unsupported pbkdf2 prf {prf}");
+                let mut key = vec![0; *dklen];
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(password, &hex::decode(salt)?, *c, &mut key);
+                key
+            }
+        };
+        Ok(key)
+    }
+}
+
+/// normalizes `password` as per EIP-2335, i.e. NFKD without the control codes
+fn normalize(password: &str) -> Vec<u8> {
+    password
+        .nfkd()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::signers::Signer;
+
+    // the PBKDF2 test vector of EIP-2335
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto": {
+            "kdf": {
+                "function": "pbkdf2",
+                "params": {
+                    "dklen": 32,
+                    "c": 262144,
+                    "prf": "hmac-sha256",
+                    "salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+                },
+                "message": ""
+            },
+            "checksum": {
+                "function": "sha256",
+                "params": {},
+                "message": "8a9f5d9912ed7e75ea794bc5a89bca5f193721d30868ade6f73043c6ea6febf1"
+            },
+            "cipher": {
+                "function": "aes-128-ctr",
+                "params": {
+                    "iv": "264daa3f303d7259501c93d997d84fe6"
+                },
+                "message": "cee03fde2af33149775b7223e7845e4fb2c8ae1792e5f99fe9ecf474cc8c16ad"
+            }
+        },
+        "description": "This is a test keystore that uses PBKDF2 to secure the secret.",
+        "pubkey": "9612d7a727c9d0a22e185a1c768478dfe919cada9266988cb32359c11f2b7b27f4ae4040902382ae2910c15e2b420d07",
+        "path": "m/12381/60/0/0",
+        "uuid": "64625def-3331-4eea-ab6f-782f3ed16a83",
+        "version": 4
+    }"#;
+    const PASSWORD: &str = "𝔱𝔢𝔰𝔱𝔭𝔞𝔰𝔰𝔴𝔬𝔯𝔡🔑";
+    const SECRET: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+    #[test]
+    fn normalize_password() {
+        assert_eq!(normalize(PASSWORD), "testpassword🔑".as_bytes());
+        assert_eq!(normalize("pass\u{7f}word\u{0}"), b"password");
+    }
+
+    #[test]
+    fn decrypt_keystore() {
+        let keystore: Keystore = serde_json::from_str(PBKDF2_KEYSTORE).unwrap();
+
+        let secret_key = keystore.decrypt(PASSWORD).unwrap();
+        let expected = SecretKey::try_from(hex::decode(SECRET).unwrap().as_slice()).unwrap();
+        assert_eq!(secret_key.public_key(), expected.public_key());
+
+        assert!(keystore.decrypt("wrong password").is_err());
+    }
+
+    #[test]
+    fn load_keys_from_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, format!("{PASSWORD}\n")).unwrap();
+        let keystore = dir.path().join("keystore.json");
+        fs::write(&keystore, PBKDF2_KEYSTORE).unwrap();
+        let source = KeystoreSource {
+            keystore,
+            password_file: password_file.clone(),
+        };
+        source.bls_secret_key().unwrap();
+
+        let mut rng = rand::thread_rng();
+        let (wallet, name) =
+            LocalWallet::new_keystore(dir.path(), &mut rng, "secret", None).unwrap();
+        fs::write(&password_file, "secret\n").unwrap();
+        let source = KeystoreSource {
+            keystore: dir.path().join(name),
+            password_file,
+        };
+        assert_eq!(source.wallet().unwrap().address(), wallet.address());
+    }
+}
@@ -2,9 +2,16 @@ pub mod builder;
 pub mod bundle;
 pub mod config;
 pub mod coordinator;
+pub mod duties;
 pub mod executor;
+pub mod keystore;
 mod mev_boost_relay_json;
 pub mod relay_endpoint;
 pub mod reth_mev_rs_convert;
+pub mod rpc;
 pub mod signing;
+pub mod ssz;
+#[cfg(test)]
+mod test_utils;
 pub mod types;
+pub mod web3signer;
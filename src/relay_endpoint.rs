@@ -1,6 +1,10 @@
-use crate::types::as_string;
-use crate::types::SignedBidSubmission;
-use anyhow::{Context, Result};
+use crate::signing::verify_builder_message;
+use crate::ssz;
+use crate::types::{as_string, try_bytes_from_hex_str};
+use crate::types::{BidTrace, BlobsBundle, ExecutionPayload, Fork};
+use anyhow::{anyhow, Context, Result};
+use ethereum_consensus::builder::ValidatorRegistration;
+use ethereum_consensus::primitives::{BlsPublicKey, BlsSignature, ExecutionAddress};
 use flate2::{write::GzEncoder, Compression};
 use reqwest::header;
 use reth_primitives::{hex, Address};
@@ -18,7 +22,7 @@ use tokio::time::sleep;
 type PublicKey = B384;
 
 // TODO only deserialze?
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct EntryMessage {
     pub fee_recipient: Address,
     #[serde(with = "as_string")]
@@ -28,13 +32,34 @@ pub struct EntryMessage {
     pub pubkey: PublicKey,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Entry {
     pub message: EntryMessage,
     pub signature: String,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl Entry {
+    /// verifies that the proposer signed the registration. the relay serves the registration, so
+    /// we must not trust its fee recipient and gas limit otherwise.
+    pub fn verify(&self, context: &ethereum_consensus::state_transition::Context) -> Result<()> {
+        let signature = try_bytes_from_hex_str(&self.signature)?;
+        let signature = BlsSignature::try_from(signature.as_slice())?;
+
+        let public_key: [u8; 48] = self.message.pubkey.to_be_bytes();
+        let public_key = BlsPublicKey::try_from(&public_key[..])?;
+
+        let mut registration = ValidatorRegistration {
+            fee_recipient: ExecutionAddress::try_from(self.message.fee_recipient.as_bytes())?,
+            gas_limit: self.message.gas_limit,
+            timestamp: self.message.timestamp,
+            public_key: public_key.clone(),
+        };
+        verify_builder_message(&mut registration, &signature, &public_key, context)
+            .context("invalid signature of validator registration")
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Validator {
     #[serde(with = "as_string")]
     pub slot: u64,
@@ -49,10 +74,29 @@ pub struct SendBlockStatus {
     pub message: String,
 }
 
+/// the encoding of the block submissions to a relay
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Ssz,
+}
+
+impl Encoding {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::Ssz => "application/octet-stream",
+        }
+    }
+}
+
 pub struct RelayEndpoint {
     name: String,
     url: String,
-    client: reqwest::blocking::Client,
+    client: reqwest::Client,
+    encoding: Encoding,
     is_gzip_enabled: bool,
     autorization_header: Option<String>,
     //tags: Vec<String>, // TODO support blacklist etc.
@@ -62,36 +106,59 @@ impl RelayEndpoint {
     pub fn new(
         name: &str,
         url: &str,
+        encoding: Encoding,
         is_gzip_enabled: bool,
         autorization_header: Option<String>,
     ) -> Self {
         RelayEndpoint {
             name: name.to_string(),
             url: url.to_string(),
-            client: reqwest::blocking::Client::new(),
+            client: reqwest::Client::new(),
+            encoding,
             is_gzip_enabled,
             autorization_header,
         }
     }
-    pub fn get_validators(&self) -> Result<Vec<Validator>> {
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn get_validators(&self) -> Result<Vec<Validator>> {
         let endpoint = format!("{}/relay/v1/builder/validators", self.url);
         let response: Vec<Validator> = self
             .client
             .get(endpoint)
-            .send()?
+            .send()
+            .await?
             .json()
+            .await
             .context("get validators request")?;
         Ok(response)
     }
 
-    pub fn post_block(&self, block: &SignedBidSubmission) -> Result<SendBlockStatus> {
+    /// the format in which the relay accepts block submissions
+    pub fn format(&self) -> Format {
+        Format {
+            encoding: self.encoding,
+            gzip: self.is_gzip_enabled,
+        }
+    }
+
+    /// submits the bid `message` with `signature` for the encoded `payload`
+    pub async fn post_bid(
+        &self,
+        message: &BidTrace,
+        signature: &BlsSignature,
+        payload: &EncodedPayload,
+    ) -> Result<SendBlockStatus> {
         let endpoint = format!("{}/relay/v1/builder/blocks", self.url);
-        let (body, encoding) = self.encode(&block)?;
+        let (body, encoding) = self.encode(message, signature, payload)?;
 
         let mut req_builder = self
             .client
             .post(endpoint)
-            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_TYPE, self.encoding.content_type())
             .body(body);
 
         if encoding.is_some() {
@@ -102,46 +169,190 @@ impl RelayEndpoint {
             req_builder = req_builder.header(header::AUTHORIZATION, auth);
         }
 
-        let response: SendBlockStatus = req_builder.send()?.json().context("send block request")?;
+        let response = req_builder.send().await?;
+
+        // relays respond to accepted submissions with an empty body
+        let code = response.status().as_u16() as u64;
+        let body = response.bytes().await.context("send block request")?;
+        if body.is_empty() {
+            return Ok(SendBlockStatus {
+                code,
+                message: String::new(),
+            });
+        }
+
+        let response: SendBlockStatus =
+            serde_json::from_slice(&body).context("send block request")?;
 
         Ok(response)
     }
 
-    fn encode(&self, bid: &SignedBidSubmission) -> Result<(Vec<u8>, Option<&str>)> {
-        let payload = serde_json::to_vec(bid).context("marshal block json")?;
+    /// encodes the submission of `payload` by prepending the encoded bid to the encoded payload
+    fn encode(
+        &self,
+        message: &BidTrace,
+        signature: &BlsSignature,
+        payload: &EncodedPayload,
+    ) -> Result<(Vec<u8>, Option<&str>)> {
+        let format = self.format();
+        let tail = payload
+            .tails
+            .get(&format)
+            .ok_or_else(|| anyhow!("payload is not encoded in format {format:?}"))?;
+
+        let head = match self.encoding {
+            Encoding::Json => json_head(message, signature).context("marshal block json")?,
+            Encoding::Ssz => ssz::encode_head(message, signature, payload.fork, payload.ssz_len)
+                .context("marshal block ssz")?,
+        };
 
-        if self.is_gzip_enabled {
-            let mut compressed_buffer = Vec::new();
-            let mut gz = GzEncoder::new(&mut compressed_buffer, Compression::fast());
-            gz.write_all(&payload).context("write payload bytes")?;
-            gz.finish().context("gzip finish")?;
-            Ok((compressed_buffer, Some("gzip")))
+        let mut body = if self.is_gzip_enabled {
+            gzip(&head)?
         } else {
-            Ok((payload, None))
+            head
+        };
+        body.extend_from_slice(tail);
+
+        Ok((body, self.is_gzip_enabled.then_some("gzip")))
+    }
+}
+
+/// the format of the block submissions to a relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub gzip: bool,
+}
+
+/// the encodings of an execution payload (and its blobs bundle), shared by the submissions of the
+/// payload to all relays. only the bid and its signature differ between the submissions, so each
+/// submission consists of the encoded bid followed by the encoded payload.
+///
+/// NOTE: a gzip stream may consist of multiple members (RFC 1952), so the gzipped bid can precede
+/// the gzipped payload as well.
+pub struct EncodedPayload {
+    fork: Fork,
+    /// the length of the SSZ-encoded execution payload
+    ssz_len: usize,
+    tails: HashMap<Format, Vec<u8>>,
+}
+
+impl EncodedPayload {
+    /// encodes `execution_payload` and `blobs_bundle` in each of `formats`
+    pub fn new(
+        execution_payload: &ExecutionPayload,
+        blobs_bundle: &BlobsBundle,
+        formats: impl IntoIterator<Item = Format>,
+    ) -> Result<Self> {
+        let fork = execution_payload.fork();
+        let formats: HashSet<Format> = formats.into_iter().collect();
+
+        let mut tails = HashMap::new();
+        let mut ssz_len = 0;
+        for encoding in [Encoding::Json, Encoding::Ssz] {
+            let gzip_formats = [false, true].map(|gzip| Format { encoding, gzip });
+            if !gzip_formats.iter().any(|format| formats.contains(format)) {
+                continue;
+            }
+
+            let tail = match encoding {
+                Encoding::Json => {
+                    json_tail(execution_payload, blobs_bundle).context("marshal block json")?
+                }
+                Encoding::Ssz => {
+                    let mut tail =
+                        ssz::encode_payload(execution_payload).context("marshal block ssz")?;
+                    ssz_len = tail.len();
+                    if fork == Fork::Deneb {
+                        let blobs_bundle =
+                            ssz::encode_blobs_bundle(blobs_bundle).context("marshal block ssz")?;
+                        tail.extend_from_slice(&blobs_bundle);
+                    }
+                    tail
+                }
+            };
+
+            let [plain, gzipped] = gzip_formats;
+            if formats.contains(&gzipped) {
+                tails.insert(gzipped, gzip(&tail)?);
+            }
+            if formats.contains(&plain) {
+                tails.insert(plain, tail);
+            }
         }
+
+        Ok(Self {
+            fork,
+            ssz_len,
+            tails,
+        })
     }
 }
 
+/// the JSON of a submission up to the execution payload. the fields of the bid precede the
+/// payload, so that the payload can be encoded once for all relays.
+fn json_head(message: &BidTrace, signature: &BlsSignature) -> Result<Vec<u8>> {
+    let mut head = b"{\"message\":".to_vec();
+    serde_json::to_writer(&mut head, message)?;
+    head.extend_from_slice(b",\"signature\":");
+    serde_json::to_writer(&mut head, signature)?;
+    head.push(b',');
+    Ok(head)
+}
+
+/// the JSON of a submission from the execution payload onwards
+fn json_tail(execution_payload: &ExecutionPayload, blobs_bundle: &BlobsBundle) -> Result<Vec<u8>> {
+    let mut tail = b"\"execution_payload\":".to_vec();
+    serde_json::to_writer(&mut tail, execution_payload)?;
+    if execution_payload.fork() == Fork::Deneb {
+        tail.extend_from_slice(b",\"blobs_bundle\":");
+        serde_json::to_writer(&mut tail, blobs_bundle)?;
+    }
+    tail.push(b'}');
+    Ok(tail)
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed_buffer = Vec::new();
+    let mut gz = GzEncoder::new(&mut compressed_buffer, Compression::fast());
+    gz.write_all(data).context("write payload bytes")?;
+    gz.finish().context("gzip finish")?;
+    Ok(compressed_buffer)
+}
+
 #[cfg(test)]
 mod tests {
+    use flate2::read::MultiGzDecoder;
     use reth_primitives::{BlockHash, Bloom, TransactionSigned, Withdrawal};
     use reth_rlp::Decodable;
+    use std::io::Read;
 
     use crate::{
         mev_boost_relay_json::SEND_BLOCK_REQUEST_EXAMPLE_JSON,
-        types::{tx_signed_to_bytes, ExecutionPayload, WithdrawalMevBoost},
+        signing::sign_builder_message,
+        test_utils::RAW_TX,
+        types::{
+            tx_signed_to_bytes, ExecutionPayload, ExecutionPayloadDeneb, SignedBidSubmission,
+            WithdrawalMevBoost,
+        },
     };
 
     use super::*;
 
     fn setup_endpoint() -> RelayEndpoint {
-        RelayEndpoint::new("ultrasound", "https://relay.ultrasound.money", false, None)
+        RelayEndpoint::new(
+            "ultrasound",
+            "https://relay.ultrasound.money",
+            Encoding::Json,
+            false,
+            None,
+        )
     }
 
-    #[test]
-    fn get_validator_relay_response() -> Result<(), Box<dyn Error>> {
+    #[tokio::test]
+    async fn get_validator_relay_response() -> Result<(), Box<dyn Error>> {
         let endpoint = setup_endpoint();
-        let response: Vec<Validator> = endpoint.get_validators().unwrap();
+        let response: Vec<Validator> = endpoint.get_validators().await.unwrap();
 
         // Now `response` is a Vec<GetValidatorRelayResponse>
         println!("{:#?}", response);
@@ -149,12 +360,15 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn send_invalid_block() -> Result<()> {
+    #[tokio::test]
+    async fn send_invalid_block() -> Result<()> {
         let endpoint = setup_endpoint();
 
         let bid: SignedBidSubmission = serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON)?;
-        let response = endpoint.post_block(&bid);
+        let (message, execution_payload, blobs_bundle, signature) = bid.into_parts();
+        let payload =
+            EncodedPayload::new(&execution_payload, &blobs_bundle, Some(endpoint.format()))?;
+        let response = endpoint.post_bid(&message, &signature, &payload).await;
 
         let expected_response = SendBlockStatus {
             code: 400,
@@ -174,7 +388,7 @@ mod tests {
 
     #[test]
     fn serialize_execution_payload() -> Result<()> {
-        let execution_payload = ExecutionPayload {
+        let execution_payload = ExecutionPayload::Deneb(ExecutionPayloadDeneb {
             parent_hash: BlockHash::default(),
             fee_recipient: Address::default(),
             state_root: reth_primitives::H256::default(),
@@ -199,7 +413,9 @@ mod tests {
                 address: Address::default(),
                 amount: 1,
             }],
-        };
+            blob_gas_used: 1,
+            excess_blob_gas: 1,
+        });
 
         let serialized = serde_json::to_string_pretty(&execution_payload)?;
         println!("{}", serialized);
@@ -207,9 +423,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_per_relay_encoding() -> Result<()> {
+        let bid: SignedBidSubmission = serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON)?;
+        let (message, execution_payload, blobs_bundle, signature) = bid.clone().into_parts();
+
+        let endpoints: Vec<_> = [Encoding::Json, Encoding::Ssz]
+            .into_iter()
+            .flat_map(|encoding| {
+                [false, true]
+                    .map(|gzip| RelayEndpoint::new("relay", "http://relay", encoding, gzip, None))
+            })
+            .collect();
+        let payload = EncodedPayload::new(
+            &execution_payload,
+            &blobs_bundle,
+            endpoints.iter().map(|endpoint| endpoint.format()),
+        )?;
+
+        for endpoint in &endpoints {
+            let (mut body, content_encoding) = endpoint.encode(&message, &signature, &payload)?;
+            if endpoint.is_gzip_enabled {
+                assert_eq!(content_encoding, Some("gzip"));
+                let mut decompressed = Vec::new();
+                MultiGzDecoder::new(&body[..]).read_to_end(&mut decompressed)?;
+                body = decompressed;
+            } else {
+                assert_eq!(content_encoding, None);
+            }
+
+            match endpoint.encoding {
+                Encoding::Json => assert_eq!(
+                    serde_json::from_slice::<serde_json::Value>(&body)?,
+                    serde_json::to_value(&bid)?
+                ),
+                Encoding::Ssz => assert_eq!(body, ssz::encode(&bid)?),
+            }
+        }
+
+        // the payload is only encoded in the formats requested
+        let payload = EncodedPayload::new(&execution_payload, &blobs_bundle, None)?;
+        assert!(endpoints[0].encode(&message, &signature, &payload).is_err());
+
+        assert_eq!(Encoding::Ssz.content_type(), "application/octet-stream");
+
+        Ok(())
+    }
+
     #[test]
     fn encode_mevboost_tx() -> Result<()> {
-        let mut bytes = hex::decode("02f878831469668303f51d843b9ac9f9843b9aca0082520894c93269b73096998db66be0441e836d873535cb9c8894a19041886f000080c001a031cc29234036afbf9a1fb9476b463367cb1f957ac0b919b69bbc798436e604aaa018c4e9c3914eb27aadd0b91e10b18655739fcf8c1fc398763a9f1beecb8ddc86")?;
+        let bytes = hex::decode(RAW_TX)?;
         let mut slice: &[u8] = &bytes;
 
         let tx = TransactionSigned::decode(&mut slice);
@@ -218,4 +481,42 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn verify_entry_signature() {
+        let secret_key =
+            ethereum_consensus::crypto::SecretKey::random(&mut rand::thread_rng()).unwrap();
+        let public_key = secret_key.public_key();
+        let fee_recipient = Address::repeat_byte(1);
+
+        let mut registration = ValidatorRegistration {
+            fee_recipient: ExecutionAddress::try_from(fee_recipient.as_bytes()).unwrap(),
+            gas_limit: 30_000_000,
+            timestamp: 1_700_000_000,
+            public_key: public_key.clone(),
+        };
+        let context = ethereum_consensus::state_transition::Context::for_mainnet();
+        let signature = sign_builder_message(&mut registration, &secret_key, &context).unwrap();
+
+        let entry: Entry = serde_json::from_value(serde_json::json!({
+            "message": {
+                "fee_recipient": fee_recipient,
+                "gas_limit": "30000000",
+                "timestamp": "1700000000",
+                "pubkey": public_key,
+            },
+            "signature": signature,
+        }))
+        .unwrap();
+        entry.verify(&context).unwrap();
+
+        // a relay must not be able to redirect the payment to the proposer
+        let mut redirected = entry.clone();
+        redirected.message.fee_recipient = Address::repeat_byte(2);
+        assert!(redirected.verify(&context).is_err());
+
+        let mut malformed = entry;
+        malformed.signature = "0x1234".to_string();
+        assert!(malformed.verify(&context).is_err());
+    }
 }
@@ -0,0 +1,330 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bundle::{BlobSidecars, Bundle, BundleId, BundleRequest};
+
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+    server::{ServerBuilder, ServerHandle},
+    types::{
+        error::{INTERNAL_ERROR_CODE, INVALID_PARAMS_CODE},
+        ErrorObject, ErrorObjectOwned,
+    },
+};
+use reth_primitives::{
+    keccak256, Bytes, PooledTransactionsElement, Transaction, TransactionSigned,
+    TransactionSignedEcRecovered, H256, U64,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// the furthest into the future that the eligibility of a bundle may end, in seconds
+const MAX_TIMESTAMP_HORIZON: u64 = 365 * 24 * 60 * 60;
+
+/// the Flashbots `eth_sendBundle` request
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendBundleRequest {
+    /// raw, signed, EIP-2718 encoded transactions
+    pub txs: Vec<Bytes>,
+    /// the block number that the bundle targets
+    pub block_number: U64,
+    /// the last block number that the bundle targets. if absent, then the bundle only targets
+    /// `block_number`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_block_number: Option<U64>,
+    /// the minimum timestamp (inclusive) at which the bundle is eligible
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_timestamp: Option<u64>,
+    /// the maximum timestamp (inclusive) at which the bundle is eligible
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_timestamp: Option<u64>,
+    /// hashes of the transactions that are allowed to revert
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reverting_tx_hashes: Vec<H256>,
+    /// a UUID that allows the bundle to be replaced or cancelled by later requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement_uuid: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendBundleResponse {
+    pub bundle_hash: H256,
+}
+
+/// the Flashbots `eth_cancelBundle` request
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelBundleRequest {
+    pub replacement_uuid: Uuid,
+}
+
+#[rpc(server, namespace = "eth")]
+pub trait BundleApi {
+    /// submits a bundle to the builder
+    #[method(name = "sendBundle")]
+    async fn send_bundle(&self, request: SendBundleRequest) -> RpcResult<SendBundleResponse>;
+
+    /// cancels the bundle with the given replacement UUID
+    #[method(name = "cancelBundle")]
+    async fn cancel_bundle(&self, request: CancelBundleRequest) -> RpcResult<()>;
+}
+
+/// the searcher-facing bundle API. bundles received are forwarded to the bundle flow of the
+/// builder (see [`crate::builder::Builder::start`]).
+#[derive(Clone)]
+pub struct BundleRpc {
+    next_id: Arc<AtomicU64>,
+    bundle_flow: mpsc::UnboundedSender<BundleRequest>,
+}
+
+impl BundleRpc {
+    pub fn new(bundle_flow: mpsc::UnboundedSender<BundleRequest>) -> Self {
+        Self {
+            next_id: Arc::new(AtomicU64::new(0)),
+            bundle_flow,
+        }
+    }
+
+    /// starts serving the bundle API over HTTP and WS at `addr`
+    ///
+    /// returns the address that the server is bound to along with a handle to the server.
+    pub async fn start(self, addr: SocketAddr) -> anyhow::Result<(SocketAddr, ServerHandle)> {
+        let server = ServerBuilder::default().build(addr).await?;
+        let addr = server.local_addr()?;
+        let handle = server.start(self.into_rpc());
+        tracing::info!(%addr, "bundle rpc server started");
+        Ok((addr, handle))
+    }
+
+    fn next_id(&self) -> BundleId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl BundleApiServer for BundleRpc {
+    async fn send_bundle(&self, request: SendBundleRequest) -> RpcResult<SendBundleResponse> {
+        let (txs, sidecars) = decode_txs(&request.txs)?;
+        let bundle_hash = bundle_hash(&txs);
+
+        let min_block = request.block_number.to::<u64>();
+        let max_block = request
+            .max_block_number
+            .map_or(min_block, |num| num.to::<u64>());
+        if min_block > max_block {
+            return Err(invalid_params("blockNumber is greater than maxBlockNumber"));
+        }
+
+        let min_timestamp = request.min_timestamp.unwrap_or(0);
+        let max_timestamp = request.max_timestamp.unwrap_or(u64::MAX);
+        if min_timestamp > max_timestamp {
+            return Err(invalid_params("minTimestamp is greater than maxTimestamp"));
+        }
+        if let Some(max_timestamp) = request.max_timestamp {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            if max_timestamp > now.saturating_add(MAX_TIMESTAMP_HORIZON) {
+                return Err(invalid_params("maxTimestamp is too far in the future"));
+            }
+        }
+
+        let bundle = Bundle {
+            id: self.next_id(),
+            txs,
+            block_range: min_block..=max_block,
+            eligibility: min_timestamp..=max_timestamp,
+            reverting_tx_hashes: request.reverting_tx_hashes,
+            replacement_uuid: request.replacement_uuid,
+            sidecars,
+        };
+        tracing::debug!(id = bundle.id, %bundle_hash, blocks = ?bundle.block_range, "received bundle");
+
+        self.bundle_flow
+            .send(BundleRequest::Send(bundle))
+            .map_err(|_| closed())?;
+
+        Ok(SendBundleResponse { bundle_hash })
+    }
+
+    async fn cancel_bundle(&self, request: CancelBundleRequest) -> RpcResult<()> {
+        let uuid = request.replacement_uuid;
+        tracing::debug!(%uuid, "received bundle cancellation");
+
+        self.bundle_flow
+            .send(BundleRequest::Cancel(uuid))
+            .map_err(|_| closed())
+    }
+}
+
+/// decodes and recovers the signers of raw bundle transactions
+///
+/// blob transactions must be in their network form, i.e. they must carry their sidecars.
+fn decode_txs(raw: &[Bytes]) -> RpcResult<(Vec<TransactionSignedEcRecovered>, BlobSidecars)> {
+    if raw.is_empty() {
+        return Err(invalid_params("bundle contains no transactions"));
+    }
+
+    let mut txs = Vec::with_capacity(raw.len());
+    let mut sidecars = BlobSidecars::default();
+    for (i, tx) in raw.iter().enumerate() {
+        let tx = PooledTransactionsElement::decode_enveloped(tx.clone())
+            .map_err(|err| invalid_params(format!("failed to decode tx {i}: {err}")))?;
+        let tx = match tx {
+            PooledTransactionsElement::BlobTransaction(tx) => {
+                sidecars.0.insert(tx.hash, Arc::new(tx.sidecar));
+                TransactionSigned::from_transaction_and_signature(
+                    Transaction::Eip4844(tx.transaction),
+                    tx.signature,
+                )
+            }
+            tx => tx.into_transaction(),
+        };
+        let tx = tx
+            .into_ecrecovered()
+            .ok_or_else(|| invalid_params(format!("failed to recover signer of tx {i}")))?;
+        txs.push(tx);
+    }
+
+    Ok((txs, sidecars))
+}
+
+/// the bundle hash is the hash of the concatenated hashes of the bundle transactions
+fn bundle_hash(txs: &[TransactionSignedEcRecovered]) -> H256 {
+    let hashes: Vec<u8> = txs
+        .iter()
+        .flat_map(|tx| tx.hash_ref().as_bytes().to_vec())
+        .collect();
+    keccak256(hashes)
+}
+
+fn invalid_params(msg: impl Into<String>) -> ErrorObjectOwned {
+    ErrorObject::owned(INVALID_PARAMS_CODE, msg, None::<()>)
+}
+
+fn closed() -> ErrorObjectOwned {
+    ErrorObject::owned(
+        INTERNAL_ERROR_CODE,
+        "builder is not accepting bundles",
+        None::<()>,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::RAW_TX;
+    use reth_primitives::hex;
+
+    fn raw_tx() -> Bytes {
+        Bytes::from(hex::decode(RAW_TX).unwrap())
+    }
+
+    #[test]
+    fn deserialize_send_bundle_request() {
+        let json = format!(
+            r#"{{"txs":["0x{RAW_TX}"],"blockNumber":"0x10","minTimestamp":1,"maxTimestamp":2}}"#
+        );
+        let request: SendBundleRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(request.txs, vec![raw_tx()]);
+        assert_eq!(request.block_number, U64::from(16));
+        assert_eq!(request.min_timestamp, Some(1));
+        assert_eq!(request.max_timestamp, Some(2));
+    }
+
+    #[tokio::test]
+    async fn send_bundle_forwards_to_bundle_flow() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let rpc = BundleRpc::new(tx);
+
+        let request = SendBundleRequest {
+            txs: vec![raw_tx(), raw_tx()],
+            block_number: U64::from(1),
+            min_timestamp: Some(10),
+            ..Default::default()
+        };
+        let response = rpc.send_bundle(request.clone()).await.unwrap();
+        let _ = rpc.send_bundle(request).await.unwrap();
+
+        let Some(BundleRequest::Send(first)) = rx.recv().await else {
+            panic!("expected bundle")
+        };
+        let Some(BundleRequest::Send(second)) = rx.recv().await else {
+            panic!("expected bundle")
+        };
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.txs.len(), 2);
+        assert_eq!(first.block_range, 1..=1);
+        assert_eq!(first.eligibility, 10..=u64::MAX);
+        assert_eq!(response.bundle_hash, bundle_hash(&first.txs));
+    }
+
+    #[tokio::test]
+    async fn send_bundle_rejects_invalid_bundles() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let rpc = BundleRpc::new(tx);
+
+        let empty = SendBundleRequest::default();
+        assert!(rpc.send_bundle(empty).await.is_err());
+
+        let garbage = SendBundleRequest {
+            txs: vec![Bytes::from(vec![0x02, 0x01])],
+            ..Default::default()
+        };
+        assert!(rpc.send_bundle(garbage).await.is_err());
+
+        let inverted = SendBundleRequest {
+            txs: vec![raw_tx()],
+            min_timestamp: Some(2),
+            max_timestamp: Some(1),
+            ..Default::default()
+        };
+        assert!(rpc.send_bundle(inverted).await.is_err());
+
+        let inverted_blocks = SendBundleRequest {
+            txs: vec![raw_tx()],
+            block_number: U64::from(2),
+            max_block_number: Some(U64::from(1)),
+            ..Default::default()
+        };
+        assert!(rpc.send_bundle(inverted_blocks).await.is_err());
+
+        let far_future = SendBundleRequest {
+            txs: vec![raw_tx()],
+            max_timestamp: Some(10_000_000_000),
+            ..Default::default()
+        };
+        assert!(rpc.send_bundle(far_future).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cancel_bundle_follows_send_bundle() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let rpc = BundleRpc::new(tx);
+
+        let send = SendBundleRequest {
+            txs: vec![raw_tx()],
+            replacement_uuid: Some(Uuid::from_u128(1)),
+            ..Default::default()
+        };
+        rpc.send_bundle(send).await.unwrap();
+        let json = r#"{"replacementUuid":"00000000-0000-0000-0000-000000000001"}"#;
+        let request: CancelBundleRequest = serde_json::from_str(json).unwrap();
+        rpc.cancel_bundle(request).await.unwrap();
+
+        // the cancellation reaches the builder after the bundle that it cancels
+        assert!(matches!(rx.recv().await, Some(BundleRequest::Send(_))));
+        assert_eq!(
+            rx.recv().await,
+            Some(BundleRequest::Cancel(Uuid::from_u128(1)))
+        );
+    }
+}
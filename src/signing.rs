@@ -1,35 +1,265 @@
-use anyhow::Result;
-use ethers::types::H256;
+use anyhow::{bail, Context as _, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use ssz_rs::SimpleSerialize;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use ethereum_consensus::{
-    builder::{compute_builder_domain, ValidatorRegistration},
-    clock::get_current_unix_time_in_secs,
+    builder::compute_builder_domain,
     crypto::SecretKey,
-    primitives::{BlsPublicKey, BlsSignature, Root, Slot, U256},
-    signing::sign_with_domain,
+    primitives::{BlsPublicKey, BlsSignature, Domain, Root},
+    signing::{sign_with_domain, verify_signed_data},
     state_transition::Context,
 };
-use mev_rs::ValidatorRegistry;
 
-#[derive(Clone)]
+use crate::types::{try_bytes_from_hex_str, BidTrace};
+
+const MAINNET_GENESIS_VALIDATORS_ROOT: &str =
+    "0x4b363db94e286120d76eb905340fdd4e54bfe9f06bf33ff6cf5ad27f511bfe95";
+const GOERLI_GENESIS_VALIDATORS_ROOT: &str =
+    "0x043db0d9a83813551ee2f33450d23797757d430911a9320530ad8a0eabc43efb";
+const SEPOLIA_GENESIS_VALIDATORS_ROOT: &str =
+    "0xd8ea171f3c94aea21ebc42a1ed61052acf3f9209c00e4efbaaddac09ed9b8078";
+const HOLESKY_GENESIS_VALIDATORS_ROOT: &str =
+    "0x9143aa7c615a7f7115e2b6aac319c03529df8242ae705fba9df39b79c59fa8b1";
+
+const MAINNET_GENESIS_TIME: u64 = 1606824023;
+const GOERLI_GENESIS_TIME: u64 = 1616508000;
+const SEPOLIA_GENESIS_TIME: u64 = 1655733600;
+const HOLESKY_GENESIS_TIME: u64 = 1695902400;
+
+/// the beacon chain network that we build for, which determines the signing domains
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Goerli,
+    Sepolia,
+    Holesky,
+    /// a network with the consensus spec (i.e. `config.yaml`) at `spec`
+    #[serde(untagged)]
+    Custom {
+        spec: PathBuf,
+        genesis_validators_root: Root,
+        /// the unix time of the genesis of the beacon chain
+        genesis_time: u64,
+    },
+}
+
+impl Network {
+    /// returns the consensus spec of the network
+    pub fn context(&self) -> Result<Context> {
+        let context = match self {
+            Network::Mainnet => Context::for_mainnet(),
+            Network::Goerli => Context::for_goerli(),
+            Network::Sepolia => Context::for_sepolia(),
+            Network::Holesky => Context::for_holesky(),
+            Network::Custom { spec, .. } => Context::try_from_file(spec)
+                .with_context(|| format!("failed to load consensus spec {}", spec.display()))?,
+        };
+        Ok(context)
+    }
+
+    pub fn genesis_validators_root(&self) -> Result<Root> {
+        let root = match self {
+            Network::Mainnet => MAINNET_GENESIS_VALIDATORS_ROOT,
+            Network::Goerli => GOERLI_GENESIS_VALIDATORS_ROOT,
+            Network::Sepolia => SEPOLIA_GENESIS_VALIDATORS_ROOT,
+            Network::Holesky => HOLESKY_GENESIS_VALIDATORS_ROOT,
+            Network::Custom {
+                genesis_validators_root,
+                ..
+            } => return Ok(*genesis_validators_root),
+        };
+        Ok(Root::try_from(try_bytes_from_hex_str(root)?.as_slice())?)
+    }
+
+    /// returns the unix time of the genesis of the beacon chain
+    pub fn genesis_time(&self) -> u64 {
+        match self {
+            Network::Mainnet => MAINNET_GENESIS_TIME,
+            Network::Goerli => GOERLI_GENESIS_TIME,
+            Network::Sepolia => SEPOLIA_GENESIS_TIME,
+            Network::Holesky => HOLESKY_GENESIS_TIME,
+            Network::Custom { genesis_time, .. } => *genesis_time,
+        }
+    }
+}
+
+/// signs the bids of a builder, either in-process or with a remote signer
+#[async_trait]
+pub trait BidSigner: Send + Sync {
+    /// the public key of the builder
+    fn public_key(&self) -> &BlsPublicKey;
 
-pub struct Inner {
+    /// signs `bid`, which must be a bid of this builder
+    async fn sign_bid(&self, bid: &mut BidTrace) -> Result<BlsSignature>;
+}
+
+/// signs the bids of the builder on a network
+#[derive(Clone)]
+pub struct BuilderSigner {
     secret_key: SecretKey,
     public_key: BlsPublicKey,
     genesis_validators_root: Root,
-    // validator_registry: ValidatorRegistry,
     context: Arc<Context>,
+    /// the builder domain of the network, which is the same for all bids
+    domain: Domain,
+}
+
+impl BuilderSigner {
+    pub fn new(secret_key: SecretKey, network: &Network) -> Result<Self> {
+        let context = network.context()?;
+        Ok(Self {
+            public_key: secret_key.public_key(),
+            secret_key,
+            genesis_validators_root: network.genesis_validators_root()?,
+            domain: compute_builder_domain(&context)?,
+            context: Arc::new(context),
+        })
+    }
+
+    pub fn public_key(&self) -> &BlsPublicKey {
+        &self.public_key
+    }
+
+    pub fn genesis_validators_root(&self) -> Root {
+        self.genesis_validators_root
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    /// signs `bid`, which must be a bid of this builder
+    pub fn sign_bid(&self, bid: &mut BidTrace) -> Result<BlsSignature> {
+        if bid.builder_public_key != self.public_key {
+            bail!(
+                "bid of builder {:?} rather than {:?}",
+                bid.builder_public_key,
+                self.public_key
+            );
+        }
+        Ok(sign_with_domain(bid, &self.secret_key, self.domain)?)
+    }
+
+    /// verifies that `signature` is the signature of this builder over `bid`
+    pub fn verify_bid(&self, bid: &mut BidTrace, signature: &BlsSignature) -> Result<()> {
+        verify_signed_data(bid, signature, &self.public_key, self.domain)?;
+        Ok(())
+    }
 }
 
+#[async_trait]
+impl BidSigner for BuilderSigner {
+    fn public_key(&self) -> &BlsPublicKey {
+        &self.public_key
+    }
+
+    async fn sign_bid(&self, bid: &mut BidTrace) -> Result<BlsSignature> {
+        BuilderSigner::sign_bid(self, bid)
+    }
+}
+
+/// signs `message` with `signing_key` in the builder domain of the network of `context`
 pub fn sign_builder_message<T: SimpleSerialize>(
     message: &mut T,
     signing_key: &SecretKey,
+    context: &Context,
 ) -> Result<BlsSignature> {
-    // TODO can be goerli
-    let context = Context::for_mainnet();
-    let domain = compute_builder_domain(&context)?;
+    let domain = compute_builder_domain(context)?;
     let signature = sign_with_domain(message, signing_key, domain)?;
     Ok(signature)
 }
+
+/// verifies that `signature` is the signature of `public_key` over `message` in the builder domain
+/// of the network of `context`
+pub fn verify_builder_message<T: SimpleSerialize>(
+    message: &mut T,
+    signature: &BlsSignature,
+    public_key: &BlsPublicKey,
+    context: &Context,
+) -> Result<()> {
+    let domain = compute_builder_domain(context)?;
+    verify_signed_data(message, signature, public_key, domain)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethereum_consensus::builder::ValidatorRegistration;
+
+    #[test]
+    fn network_from_config() {
+        let network: Network = serde_json::from_str(r#""holesky""#).unwrap();
+        assert_eq!(network, Network::Holesky);
+
+        let network: Network = serde_json::from_value(serde_json::json!({
+            "spec": "/etc/evangelion/config.yaml",
+            "genesis_validators_root": format!("0x{}", "11".repeat(32)),
+            "genesis_time": 1000,
+        }))
+        .unwrap();
+        assert_eq!(network.genesis_time(), 1000);
+        assert_eq!(
+            network.genesis_validators_root().unwrap(),
+            Root::try_from([0x11; 32].as_slice()).unwrap()
+        );
+        assert!(network.context().is_err());
+    }
+
+    #[test]
+    fn builder_domain_depends_on_network() {
+        let mainnet = Network::Mainnet.context().unwrap();
+        let goerli = Network::Goerli.context().unwrap();
+        assert_ne!(
+            compute_builder_domain(&mainnet).unwrap(),
+            compute_builder_domain(&goerli).unwrap()
+        );
+
+        let secret_key = SecretKey::random(&mut rand::thread_rng()).unwrap();
+        let public_key = secret_key.public_key();
+        let mut registration = ValidatorRegistration {
+            public_key: public_key.clone(),
+            ..Default::default()
+        };
+
+        // a signature for goerli is not valid on mainnet
+        let signature = sign_builder_message(&mut registration, &secret_key, &goerli).unwrap();
+        verify_builder_message(&mut registration, &signature, &public_key, &goerli).unwrap();
+        assert!(
+            verify_builder_message(&mut registration, &signature, &public_key, &mainnet).is_err()
+        );
+    }
+
+    #[test]
+    fn builder_signer_signs_bids() {
+        let secret_key = SecretKey::random(&mut rand::thread_rng()).unwrap();
+        let signer = BuilderSigner::new(secret_key.clone(), &Network::Goerli).unwrap();
+        assert_eq!(signer.public_key(), &secret_key.public_key());
+
+        let mut bid = BidTrace {
+            slot: 1,
+            builder_public_key: signer.public_key().clone(),
+            gas_limit: 30_000_000,
+            ..Default::default()
+        };
+        let signature = signer.sign_bid(&mut bid).unwrap();
+        signer.verify_bid(&mut bid, &signature).unwrap();
+
+        // the cached domain is the builder domain of the network
+        let context = Network::Goerli.context().unwrap();
+        verify_builder_message(&mut bid, &signature, signer.public_key(), &context).unwrap();
+
+        let mut tampered = bid.clone();
+        tampered.slot = 2;
+        assert!(signer.verify_bid(&mut tampered, &signature).is_err());
+
+        // the signer refuses to sign the bids of other builders
+        let mut foreign = BidTrace::default();
+        assert!(signer.sign_bid(&mut foreign).is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! SSZ containers of the block submissions to relays. relays that accept SSZ-encoded submissions
+//! decode them considerably faster than JSON.
+
+use anyhow::{anyhow, Result};
+use ethereum_consensus::primitives::BlsSignature;
+use ssz_rs::prelude::*;
+
+use crate::types::{
+    self, BidTrace, Fork, BYTES_PER_BLOB, BYTES_PER_COMMITMENT, BYTES_PER_LOGS_BLOOM,
+    BYTES_PER_PROOF, MAX_BLOB_COMMITMENTS_PER_BLOCK, MAX_BYTES_PER_TRANSACTION,
+    MAX_EXTRA_DATA_BYTES, MAX_TRANSACTIONS_PER_PAYLOAD, MAX_WITHDRAWALS_PER_PAYLOAD,
+};
+
+/// the length of the offset of a variable-size field
+const BYTES_PER_LENGTH_OFFSET: usize = 4;
+
+type Bytes32 = Vector<u8, 32>;
+type ExecutionAddress = Vector<u8, 20>;
+type Transaction = List<u8, MAX_BYTES_PER_TRANSACTION>;
+
+/// returns the SSZ encoding of `bid`
+pub fn encode(bid: &types::SignedBidSubmission) -> Result<Vec<u8>> {
+    let encoding = match bid {
+        types::SignedBidSubmission::Bellatrix(bid) => {
+            ssz_rs::serialize(&SignedBidSubmissionBellatrix::try_from(bid)?)
+        }
+        types::SignedBidSubmission::Capella(bid) => {
+            ssz_rs::serialize(&SignedBidSubmissionCapella::try_from(bid)?)
+        }
+        types::SignedBidSubmission::Deneb(bid) => {
+            ssz_rs::serialize(&SignedBidSubmissionDeneb::try_from(bid)?)
+        }
+    };
+    encoding.map_err(|err| anyhow!("failed to serialize bid: {err:?}"))
+}
+
+/// returns the SSZ encoding of `payload`
+pub fn encode_payload(payload: &types::ExecutionPayload) -> Result<Vec<u8>> {
+    let encoding = match payload {
+        types::ExecutionPayload::Bellatrix(payload) => {
+            ssz_rs::serialize(&ExecutionPayloadBellatrix::try_from(payload)?)
+        }
+        types::ExecutionPayload::Capella(payload) => {
+            ssz_rs::serialize(&ExecutionPayloadCapella::try_from(payload)?)
+        }
+        types::ExecutionPayload::Deneb(payload) => {
+            ssz_rs::serialize(&ExecutionPayloadDeneb::try_from(payload)?)
+        }
+    };
+    encoding.map_err(|err| anyhow!("failed to serialize execution payload: {err:?}"))
+}
+
+/// returns the SSZ encoding of `bundle`
+pub fn encode_blobs_bundle(bundle: &types::BlobsBundle) -> Result<Vec<u8>> {
+    ssz_rs::serialize(&BlobsBundle::try_from(bundle)?)
+        .map_err(|err| anyhow!("failed to serialize blobs bundle: {err:?}"))
+}
+
+/// returns the SSZ encoding of the fixed-size part of a submission of `fork`, which precedes the
+/// encoded execution payload (and the encoded blobs bundle for deneb) of length `payload_len`.
+///
+/// NOTE: the fixed-size part is all that differs between the submissions of the same payload.
+pub fn encode_head(
+    message: &BidTrace,
+    signature: &BlsSignature,
+    fork: Fork,
+    payload_len: usize,
+) -> Result<Vec<u8>> {
+    let mut head = ssz_rs::serialize(message)
+        .map_err(|err| anyhow!("failed to serialize bid trace: {err:?}"))?;
+    let signature = ssz_rs::serialize(signature)
+        .map_err(|err| anyhow!("failed to serialize signature: {err:?}"))?;
+
+    // the variable-size fields are replaced by their offsets
+    let num_offsets = if fork == Fork::Deneb { 2 } else { 1 };
+    let fixed_len = head.len() + num_offsets * BYTES_PER_LENGTH_OFFSET + signature.len();
+    head.extend_from_slice(&(fixed_len as u32).to_le_bytes());
+    if fork == Fork::Deneb {
+        head.extend_from_slice(&((fixed_len + payload_len) as u32).to_le_bytes());
+    }
+    head.extend_from_slice(&signature);
+
+    Ok(head)
+}
+
+fn vector<const N: usize>(bytes: &[u8]) -> Result<Vector<u8, N>> {
+    Vector::try_from(bytes.to_vec())
+        .map_err(|_| anyhow!("expected {N} bytes, got {} bytes", bytes.len()))
+}
+
+fn list<T: SimpleSerialize, const N: usize>(items: Vec<T>) -> Result<List<T, N>> {
+    let len = items.len();
+    List::try_from(items).map_err(|_| anyhow!("expected at most {N} elements, got {len}"))
+}
+
+fn uint256(value: u64) -> U256 {
+    U256::from_bytes_le(reth_primitives::U256::from(value).to_le_bytes())
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: ExecutionAddress,
+    pub amount: u64,
+}
+
+impl TryFrom<&types::WithdrawalMevBoost> for Withdrawal {
+    type Error = anyhow::Error;
+
+    fn try_from(withdrawal: &types::WithdrawalMevBoost) -> Result<Self> {
+        Ok(Self {
+            index: withdrawal.index,
+            validator_index: withdrawal.validator_index,
+            address: vector(withdrawal.address.as_bytes())?,
+            amount: withdrawal.amount,
+        })
+    }
+}
+
+fn withdrawals(
+    withdrawals: &[types::WithdrawalMevBoost],
+) -> Result<List<Withdrawal, MAX_WITHDRAWALS_PER_PAYLOAD>> {
+    list(
+        withdrawals
+            .iter()
+            .map(Withdrawal::try_from)
+            .collect::<Result<_>>()?,
+    )
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct ExecutionPayloadBellatrix {
+    pub parent_hash: Bytes32,
+    pub fee_recipient: ExecutionAddress,
+    pub state_root: Bytes32,
+    pub receipts_root: Bytes32,
+    pub logs_bloom: Vector<u8, BYTES_PER_LOGS_BLOOM>,
+    pub prev_randao: Bytes32,
+    pub block_number: u64,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub timestamp: u64,
+    pub extra_data: List<u8, MAX_EXTRA_DATA_BYTES>,
+    pub base_fee_per_gas: U256,
+    pub block_hash: Bytes32,
+    pub transactions: List<Transaction, MAX_TRANSACTIONS_PER_PAYLOAD>,
+}
+
+impl TryFrom<&types::ExecutionPayloadBellatrix> for ExecutionPayloadBellatrix {
+    type Error = anyhow::Error;
+
+    fn try_from(payload: &types::ExecutionPayloadBellatrix) -> Result<Self> {
+        Ok(Self {
+            parent_hash: vector(payload.parent_hash.as_bytes())?,
+            fee_recipient: vector(payload.fee_recipient.as_bytes())?,
+            state_root: vector(payload.state_root.as_bytes())?,
+            receipts_root: vector(payload.receipts_root.as_bytes())?,
+            logs_bloom: vector(payload.logs_bloom.as_bytes())?,
+            prev_randao: vector(payload.prev_randao.as_bytes())?,
+            block_number: payload.block_number,
+            gas_limit: payload.gas_limit,
+            gas_used: payload.gas_used,
+            timestamp: payload.timestamp,
+            extra_data: list(payload.extra_data.to_vec())?,
+            base_fee_per_gas: uint256(payload.base_fee_per_gas),
+            block_hash: vector(payload.block_hash.as_bytes())?,
+            transactions: list(
+                payload
+                    .transactions
+                    .iter()
+                    .map(|tx| list(tx.to_vec()))
+                    .collect::<Result<_>>()?,
+            )?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct ExecutionPayloadCapella {
+    pub parent_hash: Bytes32,
+    pub fee_recipient: ExecutionAddress,
+    pub state_root: Bytes32,
+    pub receipts_root: Bytes32,
+    pub logs_bloom: Vector<u8, BYTES_PER_LOGS_BLOOM>,
+    pub prev_randao: Bytes32,
+    pub block_number: u64,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub timestamp: u64,
+    pub extra_data: List<u8, MAX_EXTRA_DATA_BYTES>,
+    pub base_fee_per_gas: U256,
+    pub block_hash: Bytes32,
+    pub transactions: List<Transaction, MAX_TRANSACTIONS_PER_PAYLOAD>,
+    pub withdrawals: List<Withdrawal, MAX_WITHDRAWALS_PER_PAYLOAD>,
+}
+
+impl TryFrom<&types::ExecutionPayloadCapella> for ExecutionPayloadCapella {
+    type Error = anyhow::Error;
+
+    fn try_from(payload: &types::ExecutionPayloadCapella) -> Result<Self> {
+        Ok(Self {
+            parent_hash: vector(payload.parent_hash.as_bytes())?,
+            fee_recipient: vector(payload.fee_recipient.as_bytes())?,
+            state_root: vector(payload.state_root.as_bytes())?,
+            receipts_root: vector(payload.receipts_root.as_bytes())?,
+            logs_bloom: vector(payload.logs_bloom.as_bytes())?,
+            prev_randao: vector(payload.prev_randao.as_bytes())?,
+            block_number: payload.block_number,
+            gas_limit: payload.gas_limit,
+            gas_used: payload.gas_used,
+            timestamp: payload.timestamp,
+            extra_data: list(payload.extra_data.to_vec())?,
+            base_fee_per_gas: uint256(payload.base_fee_per_gas),
+            block_hash: vector(payload.block_hash.as_bytes())?,
+            transactions: list(
+                payload
+                    .transactions
+                    .iter()
+                    .map(|tx| list(tx.to_vec()))
+                    .collect::<Result<_>>()?,
+            )?,
+            withdrawals: withdrawals(&payload.withdrawals)?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct ExecutionPayloadDeneb {
+    pub parent_hash: Bytes32,
+    pub fee_recipient: ExecutionAddress,
+    pub state_root: Bytes32,
+    pub receipts_root: Bytes32,
+    pub logs_bloom: Vector<u8, BYTES_PER_LOGS_BLOOM>,
+    pub prev_randao: Bytes32,
+    pub block_number: u64,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub timestamp: u64,
+    pub extra_data: List<u8, MAX_EXTRA_DATA_BYTES>,
+    pub base_fee_per_gas: U256,
+    pub block_hash: Bytes32,
+    pub transactions: List<Transaction, MAX_TRANSACTIONS_PER_PAYLOAD>,
+    pub withdrawals: List<Withdrawal, MAX_WITHDRAWALS_PER_PAYLOAD>,
+    pub blob_gas_used: u64,
+    pub excess_blob_gas: u64,
+}
+
+impl TryFrom<&types::ExecutionPayloadDeneb> for ExecutionPayloadDeneb {
+    type Error = anyhow::Error;
+
+    fn try_from(payload: &types::ExecutionPayloadDeneb) -> Result<Self> {
+        Ok(Self {
+            parent_hash: vector(payload.parent_hash.as_bytes())?,
+            fee_recipient: vector(payload.fee_recipient.as_bytes())?,
+            state_root: vector(payload.state_root.as_bytes())?,
+            receipts_root: vector(payload.receipts_root.as_bytes())?,
+            logs_bloom: vector(payload.logs_bloom.as_bytes())?,
+            prev_randao: vector(payload.prev_randao.as_bytes())?,
+            block_number: payload.block_number,
+            gas_limit: payload.gas_limit,
+            gas_used: payload.gas_used,
+            timestamp: payload.timestamp,
+            extra_data: list(payload.extra_data.to_vec())?,
+            base_fee_per_gas: uint256(payload.base_fee_per_gas),
+            block_hash: vector(payload.block_hash.as_bytes())?,
+            transactions: list(
+                payload
+                    .transactions
+                    .iter()
+                    .map(|tx| list(tx.to_vec()))
+                    .collect::<Result<_>>()?,
+            )?,
+            withdrawals: withdrawals(&payload.withdrawals)?,
+            blob_gas_used: payload.blob_gas_used,
+            excess_blob_gas: payload.excess_blob_gas,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct BlobsBundle {
+    pub commitments: List<Vector<u8, BYTES_PER_COMMITMENT>, MAX_BLOB_COMMITMENTS_PER_BLOCK>,
+    pub proofs: List<Vector<u8, BYTES_PER_PROOF>, MAX_BLOB_COMMITMENTS_PER_BLOCK>,
+    pub blobs: List<Vector<u8, BYTES_PER_BLOB>, MAX_BLOB_COMMITMENTS_PER_BLOCK>,
+}
+
+impl TryFrom<&types::BlobsBundle> for BlobsBundle {
+    type Error = anyhow::Error;
+
+    fn try_from(bundle: &types::BlobsBundle) -> Result<Self> {
+        Ok(Self {
+            commitments: list(
+                bundle
+                    .commitments
+                    .iter()
+                    .map(|commitment| vector(commitment))
+                    .collect::<Result<_>>()?,
+            )?,
+            proofs: list(
+                bundle
+                    .proofs
+                    .iter()
+                    .map(|proof| vector(proof))
+                    .collect::<Result<_>>()?,
+            )?,
+            blobs: list(
+                bundle
+                    .blobs
+                    .iter()
+                    .map(|blob| vector(blob))
+                    .collect::<Result<_>>()?,
+            )?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct SignedBidSubmissionBellatrix {
+    pub message: BidTrace,
+    pub execution_payload: ExecutionPayloadBellatrix,
+    pub signature: BlsSignature,
+}
+
+impl TryFrom<&types::SignedBidSubmissionBellatrix> for SignedBidSubmissionBellatrix {
+    type Error = anyhow::Error;
+
+    fn try_from(bid: &types::SignedBidSubmissionBellatrix) -> Result<Self> {
+        Ok(Self {
+            message: bid.message.clone(),
+            execution_payload: (&bid.execution_payload).try_into()?,
+            signature: bid.signature.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct SignedBidSubmissionCapella {
+    pub message: BidTrace,
+    pub execution_payload: ExecutionPayloadCapella,
+    pub signature: BlsSignature,
+}
+
+impl TryFrom<&types::SignedBidSubmissionCapella> for SignedBidSubmissionCapella {
+    type Error = anyhow::Error;
+
+    fn try_from(bid: &types::SignedBidSubmissionCapella) -> Result<Self> {
+        Ok(Self {
+            message: bid.message.clone(),
+            execution_payload: (&bid.execution_payload).try_into()?,
+            signature: bid.signature.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, SimpleSerialize)]
+pub struct SignedBidSubmissionDeneb {
+    pub message: BidTrace,
+    pub execution_payload: ExecutionPayloadDeneb,
+    pub blobs_bundle: BlobsBundle,
+    pub signature: BlsSignature,
+}
+
+impl TryFrom<&types::SignedBidSubmissionDeneb> for SignedBidSubmissionDeneb {
+    type Error = anyhow::Error;
+
+    fn try_from(bid: &types::SignedBidSubmissionDeneb) -> Result<Self> {
+        Ok(Self {
+            message: bid.message.clone(),
+            execution_payload: (&bid.execution_payload).try_into()?,
+            blobs_bundle: (&bid.blobs_bundle).try_into()?,
+            signature: bid.signature.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mev_boost_relay_json::SEND_BLOCK_REQUEST_EXAMPLE_JSON;
+
+    #[test]
+    fn encode_capella_submission() {
+        let bid: types::SignedBidSubmission =
+            serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON).unwrap();
+        let encoding = encode(&bid).unwrap();
+
+        // the fixed-size bid trace leads the encoding, followed by the offset of the
+        // variable-size execution payload and the signature
+        let message = ssz_rs::serialize(bid.message()).unwrap();
+        assert_eq!(&encoding[..message.len()], &message[..]);
+        let offset = u32::from_le_bytes(encoding[message.len()..][..4].try_into().unwrap());
+        assert_eq!(offset as usize, message.len() + 4 + 96);
+
+        let types::SignedBidSubmission::Capella(bid) = bid else {
+            panic!("example is a capella submission");
+        };
+        let payload = ExecutionPayloadCapella::try_from(&bid.execution_payload).unwrap();
+        assert_eq!(
+            &encoding[offset as usize..],
+            &ssz_rs::serialize(&payload).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn head_and_payload_make_up_submission() {
+        let bid: types::SignedBidSubmission =
+            serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON).unwrap();
+        let (message, payload, _, signature) = bid.clone().into_parts();
+
+        // capella
+        let payload = encode_payload(&payload).unwrap();
+        let mut encoding = encode_head(&message, &signature, Fork::Capella, payload.len()).unwrap();
+        encoding.extend_from_slice(&payload);
+        assert_eq!(encoding, encode(&bid).unwrap());
+
+        // deneb
+        let bid = types::SignedBidSubmission::Deneb(types::SignedBidSubmissionDeneb {
+            message: message.clone(),
+            blobs_bundle: types::BlobsBundle {
+                commitments: vec![vec![1; BYTES_PER_COMMITMENT].into()],
+                proofs: vec![vec![2; BYTES_PER_PROOF].into()],
+                blobs: vec![vec![3; BYTES_PER_BLOB].into()],
+            },
+            signature: signature.clone(),
+            ..Default::default()
+        });
+        let (_, payload, blobs_bundle, _) = bid.clone().into_parts();
+        let payload = encode_payload(&payload).unwrap();
+        let mut encoding = encode_head(&message, &signature, Fork::Deneb, payload.len()).unwrap();
+        encoding.extend_from_slice(&payload);
+        encoding.extend_from_slice(&encode_blobs_bundle(&blobs_bundle).unwrap());
+        assert_eq!(encoding, encode(&bid).unwrap());
+    }
+
+    #[test]
+    fn encode_rejects_oversized_extra_data() {
+        let payload = types::ExecutionPayloadBellatrix {
+            extra_data: vec![0; MAX_EXTRA_DATA_BYTES + 1].into(),
+            ..Default::default()
+        };
+        assert!(ExecutionPayloadBellatrix::try_from(&payload).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! test fixtures, and helpers to mock HTTP servers, e.g. relays and remote signers
+
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::sleep,
+};
+
+/// a raw, signed EIP-1559 transaction, taken from `SEND_BLOCK_REQUEST_EXAMPLE_JSON`
+pub const RAW_TX: &str = "02f878831469668303f51d843b9ac9f9843b9aca0082520894c93269b73096998db66be0441e836d873535cb9c8894a19041886f000080c001a031cc29234036afbf9a1fb9476b463367cb1f957ac0b919b69bbc798436e604aaa018c4e9c3914eb27aadd0b91e10b18655739fcf8c1fc398763a9f1beecb8ddc86";
+
+/// a request received by a mock server
+#[derive(Debug, Clone, Default)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// serves every request with `response` after `delay`, and returns the URL of the server
+pub async fn mock_server(response: String, delay: Duration) -> String {
+    recording_mock_server(response, delay).await.0
+}
+
+/// serves every request with `response` after `delay`, and returns the URL of the server along
+/// with the requests that the server receives
+pub async fn recording_mock_server(
+    response: String,
+    delay: Duration,
+) -> (String, mpsc::UnboundedReceiver<MockRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (requests, received) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let response = response.clone();
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                let _ = requests.send(read_request(&mut stream).await);
+                sleep(delay).await;
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    (format!("http://{addr}"), received)
+}
+
+/// reads an HTTP request with a content length from `stream`
+pub async fn read_request(stream: &mut TcpStream) -> MockRequest {
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        if n == 0 {
+            return MockRequest::default();
+        }
+        request.extend_from_slice(&buf[..n]);
+
+        let text = String::from_utf8_lossy(&request);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let content_length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if request.len() >= end + 4 + content_length {
+                let mut request_line = text[..end].split_whitespace();
+                return MockRequest {
+                    method: request_line.next().unwrap_or_default().to_string(),
+                    path: request_line.next().unwrap_or_default().to_string(),
+                    body: request[end + 4..end + 4 + content_length].to_vec(),
+                };
+            }
+        }
+    }
+}
+
+pub fn http_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+        body.len()
+    )
+}
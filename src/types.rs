@@ -1,13 +1,13 @@
 use core::fmt;
 
 use anyhow::{anyhow, Result};
-use ethereum_consensus::primitives::BlsSignature;
-use mev_rs::types::BidTrace;
+use ethereum_consensus::primitives::{BlsPublicKey, BlsSignature, ExecutionAddress, Hash32};
 use reth_primitives::{
-    bytes::BytesMut, Address, BlockHash, Bloom, Bytes, Signature, Transaction, TransactionSigned,
-    TxHash, Withdrawal, H160, H256, U256,
+    bytes::BytesMut, Address, BlobTransactionSidecar, Block, BlockHash, Bloom, Bytes, ChainSpec,
+    Hardfork, Signature, Transaction, TransactionSigned, TxHash, Withdrawal, H160, H256, U256,
 };
 use reth_revm_primitives::primitives::ruint::aliases::{B256, B384};
+use ssz_rs::prelude::SimpleSerialize;
 
 // From ethereum-consensus, converted to anyhow from thiserror
 const HEX_ENCODING_PREFIX: &str = "0x";
@@ -178,7 +178,7 @@ pub struct ValidatorRegistration {
     // #[serde(with = "as_string")]
     pub timestamp: u64,
     // #[serde(rename = "pubkey")]
-    pub public_key: BlsPublicKey,
+    pub public_key: B384,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -196,26 +196,29 @@ pub struct ProposerSchedule {
     pub entry: SignedValidatorRegistration,
 }
 
-type BlsPublicKey = B384;
 // type BlsSignature = B768;
 
-// #[derive(Debug, Default, Clone, SimpleSerialize, serde::Serialize, serde::Deserialize)]
-// pub struct BidTrace {
-//     #[serde(with = "as_string")]
-//     pub slot: u64,
-//     pub parent_hash: B256, // BlockHash (H256)),
-//     pub block_hash: B256,  //BlockHash (H256)),
-//     // #[serde(rename = "builder_pubkey")]
-//     pub builder_public_key: BlsPublicKey,
-//     // #[serde(rename = "proposer_pubkey")]
-//     pub proposer_public_key: BlsPublicKey,
-//     pub proposer_fee_recipient: B160, // Address (H160),
-//     // #[serde(with = "as_string")]
-//     pub gas_limit: u64,
-//     // #[serde(with = "as_string")]
-//     pub gas_used: u64,
-//     pub value: U256,
-// }
+/// the bid of a block submission, signed by the builder over its SSZ hash tree root
+#[derive(
+    Debug, Default, Clone, PartialEq, Eq, SimpleSerialize, serde::Serialize, serde::Deserialize,
+)]
+pub struct BidTrace {
+    #[serde(with = "as_string")]
+    pub slot: u64,
+    pub parent_hash: Hash32,
+    pub block_hash: Hash32,
+    #[serde(rename = "builder_pubkey")]
+    pub builder_public_key: BlsPublicKey,
+    #[serde(rename = "proposer_pubkey")]
+    pub proposer_public_key: BlsPublicKey,
+    pub proposer_fee_recipient: ExecutionAddress,
+    #[serde(with = "as_string")]
+    pub gas_limit: u64,
+    #[serde(with = "as_string")]
+    pub gas_used: u64,
+    #[serde(with = "as_string")]
+    pub value: ssz_rs::U256,
+}
 
 use reth_rlp::Encodable;
 // TODO: From ruint, remove when ruint PR merged
@@ -244,6 +247,10 @@ pub const MAX_EXTRA_DATA_BYTES: usize = 32;
 pub const MAX_BYTES_PER_TRANSACTION: usize = 1_073_741_824;
 pub const MAX_TRANSACTIONS_PER_PAYLOAD: usize = 1_048_576;
 pub const MAX_WITHDRAWALS_PER_PAYLOAD: usize = 16;
+pub const MAX_BLOB_COMMITMENTS_PER_BLOCK: usize = 4096;
+pub const BYTES_PER_BLOB: usize = 131_072;
+pub const BYTES_PER_COMMITMENT: usize = 48;
+pub const BYTES_PER_PROOF: usize = 48;
 
 // To redefine serialization/deserialization, a bit hacky
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -280,9 +287,56 @@ pub fn tx_signed_to_bytes(tx: TransactionSigned) -> reth_primitives::Bytes {
     reth_primitives::Bytes::from(&encoded[..])
 }
 
-// It is deneb
+/// the consensus forks that we can submit blocks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fork {
+    Bellatrix,
+    Capella,
+    Deneb,
+}
+
+impl Fork {
+    /// returns the fork of the consensus layer that is active for an execution payload with
+    /// `timestamp` on `chain`
+    pub fn at(chain: &ChainSpec, timestamp: u64) -> Self {
+        if chain.fork(Hardfork::Cancun).active_at_timestamp(timestamp) {
+            Fork::Deneb
+        } else if chain
+            .fork(Hardfork::Shanghai)
+            .active_at_timestamp(timestamp)
+        {
+            Fork::Capella
+        } else {
+            Fork::Bellatrix
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
-pub struct ExecutionPayload {
+pub struct ExecutionPayloadBellatrix {
+    pub parent_hash: BlockHash,
+    pub fee_recipient: Address,
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: Bloom,
+    pub prev_randao: H256,
+    #[serde(with = "as_string")]
+    pub block_number: u64,
+    #[serde(with = "as_string")]
+    pub gas_limit: u64,
+    #[serde(with = "as_string")]
+    pub gas_used: u64,
+    #[serde(with = "as_string")]
+    pub timestamp: u64,
+    pub extra_data: Bytes, // TODO: should never be more that MAX_EXTRA_DATA_BYTES
+    #[serde(with = "as_string")]
+    pub base_fee_per_gas: u64,
+    pub block_hash: BlockHash,
+    pub transactions: Vec<reth_primitives::Bytes>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ExecutionPayloadCapella {
     pub parent_hash: BlockHash,
     pub fee_recipient: Address,
     pub state_root: H256,
@@ -303,19 +357,408 @@ pub struct ExecutionPayload {
     pub block_hash: BlockHash,
     pub transactions: Vec<reth_primitives::Bytes>,
     pub withdrawals: Vec<WithdrawalMevBoost>,
-    // deneb stuff
-    // #[serde(with = "as_string")]
-    // pub data_gas_used: u64,
-    // #[serde(with = "as_string")]
-    // pub excess_data_gas: u64,
 }
 
-// This is a Frankensteined version of the SignedBidSubmission from mev-rs.
-// TODO: drop mev-rs dependency
+#[derive(Default, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ExecutionPayloadDeneb {
+    pub parent_hash: BlockHash,
+    pub fee_recipient: Address,
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: Bloom,
+    pub prev_randao: H256,
+    #[serde(with = "as_string")]
+    pub block_number: u64,
+    #[serde(with = "as_string")]
+    pub gas_limit: u64,
+    #[serde(with = "as_string")]
+    pub gas_used: u64,
+    #[serde(with = "as_string")]
+    pub timestamp: u64,
+    pub extra_data: Bytes, // TODO: should never be more that MAX_EXTRA_DATA_BYTES
+    #[serde(with = "as_string")]
+    pub base_fee_per_gas: u64,
+    pub block_hash: BlockHash,
+    pub transactions: Vec<reth_primitives::Bytes>,
+    pub withdrawals: Vec<WithdrawalMevBoost>,
+    #[serde(with = "as_string")]
+    pub blob_gas_used: u64,
+    #[serde(with = "as_string")]
+    pub excess_blob_gas: u64,
+}
+
+/// an execution payload of any of the supported forks
+///
+/// NOTE: the variants are deserialized in order, so a later fork must precede an earlier fork,
+/// whose fields are a subset of the fields of the later fork.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ExecutionPayload {
+    Deneb(ExecutionPayloadDeneb),
+    Capella(ExecutionPayloadCapella),
+    Bellatrix(ExecutionPayloadBellatrix),
+}
+
+impl ExecutionPayload {
+    /// constructs the execution payload of `fork` for `block` with hash `block_hash`
+    pub fn new(fork: Fork, block: &Block, block_hash: BlockHash) -> Result<Self> {
+        let withdrawals = || -> Result<Vec<WithdrawalMevBoost>> {
+            let withdrawals = block
+                .withdrawals
+                .clone()
+                .ok_or_else(|| anyhow!("{fork:?} payload requires withdrawals"))?;
+            Ok(withdrawals
+                .into_iter()
+                .map(WithdrawalMevBoost::from)
+                .collect())
+        };
+
+        let payload = ExecutionPayloadBellatrix {
+            parent_hash: block.parent_hash,
+            fee_recipient: block.beneficiary,
+            state_root: block.state_root,
+            receipts_root: block.receipts_root,
+            logs_bloom: block.logs_bloom,
+            prev_randao: block.mix_hash,
+            block_number: block.number,
+            gas_limit: block.gas_limit,
+            gas_used: block.gas_used,
+            timestamp: block.timestamp,
+            extra_data: block.extra_data.clone(),
+            base_fee_per_gas: block
+                .base_fee_per_gas
+                .ok_or_else(|| anyhow!("payload requires base fee"))?,
+            block_hash,
+            transactions: block.body.iter().cloned().map(tx_signed_to_bytes).collect(),
+        };
+
+        let payload = match fork {
+            Fork::Bellatrix => ExecutionPayload::Bellatrix(payload),
+            Fork::Capella => ExecutionPayload::Capella(payload.into_capella(withdrawals()?)),
+            Fork::Deneb => ExecutionPayload::Deneb(
+                payload.into_capella(withdrawals()?).into_deneb(
+                    block
+                        .blob_gas_used
+                        .ok_or_else(|| anyhow!("{fork:?} payload requires blob gas used"))?,
+                    block
+                        .excess_blob_gas
+                        .ok_or_else(|| anyhow!("{fork:?} payload requires excess blob gas"))?,
+                ),
+            ),
+        };
+
+        Ok(payload)
+    }
+
+    pub fn fork(&self) -> Fork {
+        match self {
+            ExecutionPayload::Bellatrix(_) => Fork::Bellatrix,
+            ExecutionPayload::Capella(_) => Fork::Capella,
+            ExecutionPayload::Deneb(_) => Fork::Deneb,
+        }
+    }
+
+    pub fn block_hash(&self) -> BlockHash {
+        match self {
+            ExecutionPayload::Bellatrix(payload) => payload.block_hash,
+            ExecutionPayload::Capella(payload) => payload.block_hash,
+            ExecutionPayload::Deneb(payload) => payload.block_hash,
+        }
+    }
+}
+
+impl ExecutionPayloadBellatrix {
+    fn into_capella(self, withdrawals: Vec<WithdrawalMevBoost>) -> ExecutionPayloadCapella {
+        ExecutionPayloadCapella {
+            parent_hash: self.parent_hash,
+            fee_recipient: self.fee_recipient,
+            state_root: self.state_root,
+            receipts_root: self.receipts_root,
+            logs_bloom: self.logs_bloom,
+            prev_randao: self.prev_randao,
+            block_number: self.block_number,
+            gas_limit: self.gas_limit,
+            gas_used: self.gas_used,
+            timestamp: self.timestamp,
+            extra_data: self.extra_data,
+            base_fee_per_gas: self.base_fee_per_gas,
+            block_hash: self.block_hash,
+            transactions: self.transactions,
+            withdrawals,
+        }
+    }
+}
+
+impl ExecutionPayloadCapella {
+    fn into_deneb(self, blob_gas_used: u64, excess_blob_gas: u64) -> ExecutionPayloadDeneb {
+        ExecutionPayloadDeneb {
+            parent_hash: self.parent_hash,
+            fee_recipient: self.fee_recipient,
+            state_root: self.state_root,
+            receipts_root: self.receipts_root,
+            logs_bloom: self.logs_bloom,
+            prev_randao: self.prev_randao,
+            block_number: self.block_number,
+            gas_limit: self.gas_limit,
+            gas_used: self.gas_used,
+            timestamp: self.timestamp,
+            extra_data: self.extra_data,
+            base_fee_per_gas: self.base_fee_per_gas,
+            block_hash: self.block_hash,
+            transactions: self.transactions,
+            withdrawals: self.withdrawals,
+            blob_gas_used,
+            excess_blob_gas,
+        }
+    }
+}
+
+/// the blobs of the blob transactions in a payload, along with their KZG commitments and proofs
+#[derive(Default, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BlobsBundle {
+    pub commitments: Vec<Bytes>,
+    pub proofs: Vec<Bytes>,
+    pub blobs: Vec<Bytes>,
+}
+
+impl BlobsBundle {
+    /// appends the blobs of `sidecar` to the bundle
+    pub fn extend(&mut self, sidecar: &BlobTransactionSidecar) {
+        self.commitments.extend(
+            sidecar
+                .commitments
+                .iter()
+                .map(|commitment| Bytes::from(commitment.as_slice())),
+        );
+        self.proofs.extend(
+            sidecar
+                .proofs
+                .iter()
+                .map(|proof| Bytes::from(proof.as_slice())),
+        );
+        self.blobs.extend(
+            sidecar
+                .blobs
+                .iter()
+                .map(|blob| Bytes::from(blob.as_slice())),
+        );
+    }
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SignedBidSubmissionBellatrix {
+    pub message: BidTrace,
+    pub execution_payload: ExecutionPayloadBellatrix,
+    pub signature: BlsSignature,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SignedBidSubmissionCapella {
+    pub message: BidTrace,
+    pub execution_payload: ExecutionPayloadCapella,
+    pub signature: BlsSignature,
+}
+
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
-pub struct SignedBidSubmission {
+pub struct SignedBidSubmissionDeneb {
     pub message: BidTrace,
-    // TODO: support multiple forks (deneb etc.)
-    pub execution_payload: ExecutionPayload,
+    pub execution_payload: ExecutionPayloadDeneb,
+    pub blobs_bundle: BlobsBundle,
     pub signature: BlsSignature,
 }
+
+/// a block submission of any of the supported forks
+///
+/// NOTE: as for [`ExecutionPayload`], a later fork must precede an earlier fork.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum SignedBidSubmission {
+    Deneb(SignedBidSubmissionDeneb),
+    Capella(SignedBidSubmissionCapella),
+    Bellatrix(SignedBidSubmissionBellatrix),
+}
+
+impl SignedBidSubmission {
+    /// constructs the submission of `execution_payload`. the blobs bundle is only part of the
+    /// submission for deneb.
+    pub fn new(
+        message: BidTrace,
+        execution_payload: ExecutionPayload,
+        blobs_bundle: BlobsBundle,
+        signature: BlsSignature,
+    ) -> Self {
+        match execution_payload {
+            ExecutionPayload::Bellatrix(execution_payload) => {
+                SignedBidSubmission::Bellatrix(SignedBidSubmissionBellatrix {
+                    message,
+                    execution_payload,
+                    signature,
+                })
+            }
+            ExecutionPayload::Capella(execution_payload) => {
+                SignedBidSubmission::Capella(SignedBidSubmissionCapella {
+                    message,
+                    execution_payload,
+                    signature,
+                })
+            }
+            ExecutionPayload::Deneb(execution_payload) => {
+                SignedBidSubmission::Deneb(SignedBidSubmissionDeneb {
+                    message,
+                    execution_payload,
+                    blobs_bundle,
+                    signature,
+                })
+            }
+        }
+    }
+
+    pub fn fork(&self) -> Fork {
+        match self {
+            SignedBidSubmission::Bellatrix(_) => Fork::Bellatrix,
+            SignedBidSubmission::Capella(_) => Fork::Capella,
+            SignedBidSubmission::Deneb(_) => Fork::Deneb,
+        }
+    }
+
+    pub fn message(&self) -> &BidTrace {
+        match self {
+            SignedBidSubmission::Bellatrix(bid) => &bid.message,
+            SignedBidSubmission::Capella(bid) => &bid.message,
+            SignedBidSubmission::Deneb(bid) => &bid.message,
+        }
+    }
+
+    /// the inverse of [`SignedBidSubmission::new`]
+    pub fn into_parts(self) -> (BidTrace, ExecutionPayload, BlobsBundle, BlsSignature) {
+        match self {
+            SignedBidSubmission::Bellatrix(bid) => (
+                bid.message,
+                ExecutionPayload::Bellatrix(bid.execution_payload),
+                BlobsBundle::default(),
+                bid.signature,
+            ),
+            SignedBidSubmission::Capella(bid) => (
+                bid.message,
+                ExecutionPayload::Capella(bid.execution_payload),
+                BlobsBundle::default(),
+                bid.signature,
+            ),
+            SignedBidSubmission::Deneb(bid) => (
+                bid.message,
+                ExecutionPayload::Deneb(bid.execution_payload),
+                bid.blobs_bundle,
+                bid.signature,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mev_boost_relay_json::SEND_BLOCK_REQUEST_EXAMPLE_JSON;
+
+    use reth_primitives::{ChainSpecBuilder, ForkCondition, Header};
+
+    fn block(withdrawals: Option<Vec<Withdrawal>>, blob_gas: Option<u64>) -> Block {
+        Block {
+            header: Header {
+                base_fee_per_gas: Some(1),
+                blob_gas_used: blob_gas,
+                excess_blob_gas: blob_gas,
+                ..Default::default()
+            },
+            withdrawals,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fork_follows_chain_spec() {
+        let chain = ChainSpecBuilder::mainnet().paris_activated().build();
+        assert_eq!(Fork::at(&chain, 0), Fork::Bellatrix);
+
+        let chain = ChainSpecBuilder::mainnet()
+            .shanghai_activated()
+            .with_fork(Hardfork::Cancun, ForkCondition::Timestamp(100))
+            .build();
+        assert_eq!(Fork::at(&chain, 99), Fork::Capella);
+        assert_eq!(Fork::at(&chain, 100), Fork::Deneb);
+    }
+
+    #[test]
+    fn execution_payload_requires_fork_fields() {
+        let payload = ExecutionPayload::new(Fork::Bellatrix, &block(None, None), H256::zero());
+        assert_eq!(payload.unwrap().fork(), Fork::Bellatrix);
+
+        assert!(ExecutionPayload::new(Fork::Capella, &block(None, None), H256::zero()).is_err());
+        let payload =
+            ExecutionPayload::new(Fork::Capella, &block(Some(vec![]), None), H256::zero());
+        assert_eq!(payload.unwrap().fork(), Fork::Capella);
+
+        assert!(
+            ExecutionPayload::new(Fork::Deneb, &block(Some(vec![]), None), H256::zero()).is_err()
+        );
+        let payload =
+            ExecutionPayload::new(Fork::Deneb, &block(Some(vec![]), Some(1)), H256::zero());
+        assert_eq!(payload.unwrap().fork(), Fork::Deneb);
+    }
+
+    #[test]
+    fn deserialize_submission_of_each_fork() {
+        let bid: SignedBidSubmission =
+            serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON).unwrap();
+        assert_eq!(bid.fork(), Fork::Capella);
+
+        for fork in [Fork::Bellatrix, Fork::Capella, Fork::Deneb] {
+            let payload =
+                ExecutionPayload::new(fork, &block(Some(vec![]), Some(1)), H256::zero()).unwrap();
+            let bid = SignedBidSubmission::new(
+                BidTrace::default(),
+                payload,
+                BlobsBundle::default(),
+                BlsSignature::default(),
+            );
+
+            let json = serde_json::to_value(&bid).unwrap();
+            let execution_payload = &json["execution_payload"];
+            assert_eq!(
+                execution_payload.get("withdrawals").is_some(),
+                fork != Fork::Bellatrix
+            );
+            assert_eq!(
+                execution_payload.get("blob_gas_used").is_some(),
+                fork == Fork::Deneb
+            );
+            assert_eq!(json.get("blobs_bundle").is_some(), fork == Fork::Deneb);
+
+            let bid: SignedBidSubmission = serde_json::from_value(json).unwrap();
+            assert_eq!(bid.fork(), fork);
+        }
+    }
+
+    #[test]
+    fn bid_trace_round_trip() {
+        let json: serde_json::Value =
+            serde_json::from_str(SEND_BLOCK_REQUEST_EXAMPLE_JSON).unwrap();
+        let message = &json["message"];
+
+        let bid_trace: BidTrace = serde_json::from_value(message.clone()).unwrap();
+        assert_eq!(bid_trace.slot, 1);
+        assert_eq!(
+            bid_trace.value,
+            ssz_rs::U256::from_bytes_le(U256::from(1).to_le_bytes())
+        );
+        assert_eq!(&serde_json::to_value(&bid_trace).unwrap(), message);
+
+        // the fields of the bid trace are fixed size, so the encoding is the concatenation
+        let encoding = ssz_rs::serialize(&bid_trace).unwrap();
+        assert_eq!(encoding.len(), 8 + 32 + 32 + 48 + 48 + 20 + 8 + 8 + 32);
+        assert_eq!(
+            ssz_rs::deserialize::<BidTrace>(&encoding).unwrap(),
+            bid_trace
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use ethereum_consensus::{
+    builder::compute_builder_domain,
+    primitives::{BlsPublicKey, BlsSignature, Domain, Root},
+    signing::{compute_signing_root, verify_signed_data},
+};
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::signing::{BidSigner, Network};
+use crate::types::BidTrace;
+
+/// the type of the signing requests for builder bids
+const BUILDER_BID: &str = "BUILDER_BID";
+
+/// signs the bids of a builder with a remote signer that speaks the Web3Signer HTTP signing API,
+/// so that the BLS key of the builder can live on a separate host
+///
+/// NOTE: stock Web3Signer does not sign builder bids. its eth2 signing endpoint only accepts the
+/// messages that it knows how to hash (e.g. `BLOCK_V2` or `VALIDATOR_REGISTRATION`), and answers
+/// any other type with 400. the signer at the other end must be a Web3Signer-compatible service
+/// that implements the `BUILDER_BID` request type, i.e. that signs `signingRoot`, which is the
+/// signing root of `bid_trace` in the builder domain.
+pub struct Web3Signer {
+    client: reqwest::Client,
+    /// the signing endpoint of the key of the builder
+    url: String,
+    public_key: BlsPublicKey,
+    /// the builder domain of the network, which is the same for all bids
+    domain: Domain,
+}
+
+#[derive(Serialize)]
+struct SignRequest<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "signingRoot")]
+    signing_root: Root,
+    bid_trace: &'a BidTrace,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: BlsSignature,
+}
+
+impl Web3Signer {
+    /// a signer of the bids of `public_key` on `network` with the signer at `url`, which gives up
+    /// on a signature after `timeout`
+    pub fn new(
+        url: &str,
+        public_key: BlsPublicKey,
+        network: &Network,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let identifier = serde_json::to_value(&public_key)?;
+        let identifier = identifier
+            .as_str()
+            .ok_or_else(|| anyhow!("public key is not a hex string"))?;
+
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url: format!(
+                "{}/api/v1/eth2/sign/{identifier}",
+                url.trim_end_matches('/')
+            ),
+            public_key,
+            domain: compute_builder_domain(&network.context()?)?,
+        })
+    }
+}
+
+#[async_trait]
+impl BidSigner for Web3Signer {
+    fn public_key(&self) -> &BlsPublicKey {
+        &self.public_key
+    }
+
+    async fn sign_bid(&self, bid: &mut BidTrace) -> Result<BlsSignature> {
+        if bid.builder_public_key != self.public_key {
+            bail!(
+                "bid of builder {:?} rather than {:?}",
+                bid.builder_public_key,
+                self.public_key
+            );
+        }
+
+        // NOTE: the signer signs the signing root, and may check it against the bid
+        let request = SignRequest {
+            kind: BUILDER_BID,
+            signing_root: compute_signing_root(bid, self.domain)?,
+            bid_trace: bid,
+        };
+        let response = self
+            .client
+            .post(&self.url)
+            .header(header::ACCEPT, "application/json")
+            .json(&request)
+            .send()
+            .await
+            .context("remote signer request")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            // NOTE: a signer without support for builder bids rejects the request type with 400
+            if status == StatusCode::BAD_REQUEST {
+                bail!("remote signer rejected the {BUILDER_BID} request with {status}: {body}");
+            }
+            bail!("remote signer responded with {status}: {body}");
+        }
+        let SignResponse { signature } = response.json().await.context("remote signer response")?;
+
+        // we verify the signature, so that a misconfigured signer cannot make relays reject our bids
+        verify_signed_data(bid, &signature, &self.public_key, self.domain)
+            .context("invalid signature from remote signer")?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::signing::BuilderSigner;
+    use crate::test_utils::{http_response, mock_server, recording_mock_server};
+    use ethereum_consensus::crypto::SecretKey;
+
+    fn signer() -> BuilderSigner {
+        let secret_key = SecretKey::random(&mut rand::thread_rng()).unwrap();
+        BuilderSigner::new(secret_key, &Network::Mainnet).unwrap()
+    }
+
+    fn bid(signer: &BuilderSigner) -> BidTrace {
+        BidTrace {
+            slot: 1,
+            builder_public_key: signer.public_key().clone(),
+            gas_limit: 30_000_000,
+            ..Default::default()
+        }
+    }
+
+    async fn remote_signer(public_key: &BlsPublicKey, status: &str, body: &str) -> Web3Signer {
+        let url = mock_server(http_response(status, body), Duration::ZERO).await;
+        Web3Signer::new(
+            &url,
+            public_key.clone(),
+            &Network::Mainnet,
+            Duration::from_millis(500),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sign_bid_with_remote_signer() {
+        let local = signer();
+        let mut bid = bid(&local);
+        let signature = local.sign_bid(&mut bid).unwrap();
+
+        let body = serde_json::json!({ "signature": signature }).to_string();
+        let (url, mut requests) =
+            recording_mock_server(http_response("200 OK", &body), Duration::ZERO).await;
+        let remote = Web3Signer::new(
+            &url,
+            local.public_key().clone(),
+            &Network::Mainnet,
+            Duration::from_millis(500),
+        )
+        .unwrap();
+        let remote: &dyn BidSigner = &remote;
+        assert_eq!(remote.sign_bid(&mut bid).await.unwrap(), signature);
+
+        // the request names the key, and carries the signing root of the bid along with the bid
+        let request = requests.recv().await.unwrap();
+        let public_key = serde_json::to_value(local.public_key()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(
+            request.path,
+            format!("/api/v1/eth2/sign/{}", public_key.as_str().unwrap())
+        );
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let context = Network::Mainnet.context().unwrap();
+        let domain = compute_builder_domain(&context).unwrap();
+        let signing_root = compute_signing_root(&mut bid, domain).unwrap();
+        assert_eq!(body["type"], BUILDER_BID);
+        assert_eq!(
+            body["signingRoot"],
+            serde_json::to_value(signing_root).unwrap()
+        );
+        assert_eq!(body["bid_trace"], serde_json::to_value(&bid).unwrap());
+
+        // the remote signer refuses to sign the bids of other builders
+        let mut foreign = bid.clone();
+        foreign.builder_public_key = signer().public_key().clone();
+        assert!(remote.sign_bid(&mut foreign).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_remote_signature() {
+        let local = signer();
+        let mut bid = bid(&local);
+
+        // the remote signer signs with a different key
+        let other = signer();
+        let mut other_bid = self::bid(&other);
+        let signature = other.sign_bid(&mut other_bid).unwrap();
+        let body = serde_json::json!({ "signature": signature }).to_string();
+        let remote = remote_signer(local.public_key(), "200 OK", &body).await;
+        assert!(BidSigner::sign_bid(&remote, &mut bid).await.is_err());
+
+        let body = r#"{"message":"key not found"}"#;
+        let remote = remote_signer(local.public_key(), "404 Not Found", body).await;
+        assert!(BidSigner::sign_bid(&remote, &mut bid).await.is_err());
+
+        // a stock Web3Signer rejects the request type
+        let body = r#"{"message":"Unknown type"}"#;
+        let remote = remote_signer(local.public_key(), "400 Bad Request", body).await;
+        let err = BidSigner::sign_bid(&remote, &mut bid).await.unwrap_err();
+        assert!(err.to_string().contains(BUILDER_BID));
+    }
+}